@@ -0,0 +1,164 @@
+use super::winapi;
+
+use std::{ptr, mem};
+use std::os::raw;
+
+type HIMC = *mut raw::c_void;
+
+const GCS_COMPSTR: winapi::DWORD = 0x0008;
+const GCS_RESULTSTR: winapi::DWORD = 0x0800;
+pub const ISC_SHOWUICOMPOSITIONWINDOW: winapi::LPARAM = 0x8000_0000u32 as winapi::LPARAM;
+const CFS_POINT: winapi::DWORD = 0x0002;
+const CFS_EXCLUDE: winapi::DWORD = 0x0080;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct COMPOSITIONFORM {
+    dwStyle: winapi::DWORD,
+    ptCurrentPos: winapi::POINT,
+    rcArea: winapi::RECT,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct CANDIDATEFORM {
+    dwIndex: winapi::DWORD,
+    dwStyle: winapi::DWORD,
+    ptCurrentPos: winapi::POINT,
+    rcArea: winapi::RECT,
+}
+
+// Only linked on Windows, elsewhere the unused declarations leave the decoder tests buildable.
+#[cfg_attr(windows, link(name = "imm32"))]
+extern "system" {
+    fn ImmGetContext(wnd: winapi::HWND) -> HIMC;
+    fn ImmReleaseContext(wnd: winapi::HWND, imc: HIMC) -> winapi::BOOL;
+    fn ImmGetCompositionStringW(imc: HIMC, index: winapi::DWORD, buf: *mut raw::c_void, len: winapi::DWORD) -> winapi::LONG;
+    fn ImmSetCompositionWindow(imc: HIMC, form: *const COMPOSITIONFORM) -> winapi::BOOL;
+    fn ImmSetCandidateWindow(imc: HIMC, form: *const CANDIDATEFORM) -> winapi::BOOL;
+}
+
+// Receives the composition string while it changes and None once it ends.
+pub type PreeditHandler = Box<FnMut(Option<&str>)>;
+
+// Without a preedit handler the system draws the composition window and DefWindowProcW turns committed strings into
+// WM_IME_CHAR and then WM_CHAR messages. With one, the composition messages must not reach DefWindowProcW and committed
+// strings come out of handle_message instead.
+pub struct Ime {
+    caret: Option<(i32, i32, i32)>,
+    last_click: (i32, i32),
+    composition: Option<String>,
+    preedit: Option<PreeditHandler>,
+}
+
+impl Ime {
+    pub fn new() -> Ime {
+        Ime {
+            caret: None,
+            last_click: (0, 0),
+            composition: None,
+            preedit: None,
+        }
+    }
+
+    pub fn set_preedit_handler(&mut self, handler: Option<PreeditHandler>) {
+        self.preedit = handler;
+    }
+
+    pub fn draws_inline(&self) -> bool {
+        self.preedit.is_some()
+    }
+
+    pub fn set_caret(&mut self, caret: Option<(i32, i32, i32)>) {
+        self.caret = caret;
+    }
+
+    pub fn set_last_click(&mut self, x: i32, y: i32) {
+        self.last_click = (x, y);
+    }
+
+    pub fn composition(&self) -> Option<&str> {
+        self.composition.as_ref().map(|c| c.as_str())
+    }
+
+    // Returns the committed string, which is only read here when the preedit is drawn inline.
+    pub unsafe fn handle_message(&mut self, wnd: winapi::HWND, msg: winapi::UINT, lparam: winapi::LPARAM) -> String {
+        let mut committed = String::new();
+        match msg {
+            winapi::WM_IME_STARTCOMPOSITION => {
+                self.composition = Some(String::new());
+                self.notify_preedit();
+                self.place_windows(wnd);
+            }
+            winapi::WM_IME_COMPOSITION => {
+                if self.draws_inline() && (lparam as winapi::DWORD & GCS_RESULTSTR) != 0 {
+                    committed = composition_string(wnd, GCS_RESULTSTR);
+                }
+                if (lparam as winapi::DWORD & GCS_COMPSTR) != 0 {
+                    self.composition = Some(composition_string(wnd, GCS_COMPSTR));
+                    self.notify_preedit();
+                }
+                self.place_windows(wnd);
+            }
+            winapi::WM_IME_ENDCOMPOSITION => {
+                self.composition = None;
+                self.notify_preedit();
+            }
+            _ => {}
+        }
+        committed
+    }
+
+    fn notify_preedit(&mut self) {
+        if let Some(ref mut preedit) = self.preedit {
+            preedit(self.composition.as_ref().map(|c| c.as_str()));
+        }
+    }
+
+    unsafe fn place_windows(&self, wnd: winapi::HWND) {
+        // without an explicit caret the edit that was clicked last is the best guess
+        let (x, y, height) = self.caret.unwrap_or((self.last_click.0, self.last_click.1, 0));
+        let imc = ImmGetContext(wnd);
+        if imc.is_null() {
+            return;
+        }
+
+        let caret = winapi::RECT {
+            left: x,
+            top: y,
+            right: x + 1,
+            bottom: y + height,
+        };
+        let composition = COMPOSITIONFORM {
+            dwStyle: CFS_POINT,
+            ptCurrentPos: winapi::POINT { x: x, y: y },
+            rcArea: mem::zeroed(),
+        };
+        let candidate = CANDIDATEFORM {
+            dwIndex: 0,
+            dwStyle: CFS_EXCLUDE,
+            ptCurrentPos: winapi::POINT { x: x, y: y + height },
+            rcArea: caret,
+        };
+        ImmSetCompositionWindow(imc, &composition);
+        ImmSetCandidateWindow(imc, &candidate);
+        ImmReleaseContext(wnd, imc);
+    }
+}
+
+unsafe fn composition_string(wnd: winapi::HWND, index: winapi::DWORD) -> String {
+    let imc = ImmGetContext(wnd);
+    if imc.is_null() {
+        return String::new();
+    }
+
+    let bytes = ImmGetCompositionStringW(imc, index, ptr::null_mut(), 0);
+    let mut wstr: Vec<u16> = Vec::new();
+    if bytes > 0 {
+        wstr = vec![0; bytes as usize / mem::size_of::<u16>()];
+        ImmGetCompositionStringW(imc, index, wstr.as_mut_ptr() as *mut raw::c_void, bytes as winapi::DWORD);
+    }
+    ImmReleaseContext(wnd, imc);
+
+    String::from_utf16_lossy(&wstr)
+}
@@ -0,0 +1,633 @@
+use super::nuklear_rust::{NkContext, NkKey, NkButton};
+
+use super::winapi;
+
+use super::keymap::{Keymap, Modifiers};
+use super::pointer::PointerTracker;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Key { key: NkKey, down: bool },
+    Char(char),
+    Button { button: NkButton, x: i32, y: i32, down: bool },
+    Motion { x: i32, y: i32 },
+    Scroll { x: f32, y: f32 },
+    XButton { button: XButton, down: bool },
+    Resize { width: u16, height: u16 },
+    Focus(bool),
+}
+
+// Where the pointer is reported once it left the window, far enough out that nothing is hovered.
+pub const MOUSE_OUTSIDE: i32 = -32768;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XButton {
+    Back,
+    Forward,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputDecoder {
+    keymap: Keymap,
+    pressed: Vec<(i32, Vec<NkKey>)>,
+    buttons: Vec<NkButton>,
+    held_modifiers: Vec<i32>,
+    position: (i32, i32),
+    pointer: PointerTracker,
+    high_surrogate: Option<u16>,
+    scroll_lines: f32,
+    wheel_x: i32,
+    wheel_y: i32,
+}
+
+impl Default for InputDecoder {
+    fn default() -> InputDecoder {
+        InputDecoder::new()
+    }
+}
+
+impl InputDecoder {
+    pub fn new() -> InputDecoder {
+        InputDecoder {
+            keymap: Keymap::default(),
+            pressed: Vec::new(),
+            buttons: Vec::new(),
+            held_modifiers: Vec::new(),
+            position: (0, 0),
+            pointer: PointerTracker::new(),
+            high_surrogate: None,
+            scroll_lines: 1.0,
+            wheel_x: 0,
+            wheel_y: 0,
+        }
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn keymap_mut(&mut self) -> &mut Keymap {
+        &mut self.keymap
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn scroll_lines(&self) -> f32 {
+        self.scroll_lines
+    }
+
+    // Zero, negative or NaN counts would never drain the wheel accumulators and are ignored.
+    pub fn set_scroll_lines(&mut self, lines: f32) {
+        if lines > 0.0 {
+            self.scroll_lines = lines;
+        }
+    }
+
+    // With emulation Windows turns touch and pen contacts into mouse messages, pointer messages then only add panning.
+    pub fn mouse_emulation(&self) -> bool {
+        !self.pointer.drives_mouse()
+    }
+
+    pub fn set_mouse_emulation(&mut self, enabled: bool) {
+        self.pointer.set_drives_mouse(!enabled);
+    }
+
+    pub fn pan_pixels_per_line(&self) -> f32 {
+        self.pointer.pixels_per_line()
+    }
+
+    pub fn set_pan_pixels_per_line(&mut self, pixels: f32) {
+        self.pointer.set_pixels_per_line(pixels);
+    }
+
+    // Modifier state as of the last decoded key message, not the live keyboard state.
+    pub fn modifiers(&self) -> Modifiers {
+        let held = |left: i32, right: i32| self.held_modifiers.iter().any(|&vk| vk == left || vk == right);
+        Modifiers {
+            ctrl: held(winapi::VK_LCONTROL, winapi::VK_RCONTROL),
+            shift: held(winapi::VK_LSHIFT, winapi::VK_RSHIFT),
+            alt: held(winapi::VK_LMENU, winapi::VK_RMENU),
+            win: held(winapi::VK_LWIN, winapi::VK_RWIN),
+        }
+    }
+
+    pub fn any_button_down(&self) -> bool {
+        !self.buttons.is_empty()
+    }
+
+    // Releases everything reported as down, for when the window stops receiving the matching up messages.
+    pub fn release_all(&mut self, events: &mut Vec<InputEvent>) {
+        for (_, keys) in self.pressed.drain(..) {
+            for key in keys {
+                events.push(InputEvent::Key {
+                                key: key,
+                                down: false,
+                            });
+            }
+        }
+        self.release_buttons(events);
+        self.held_modifiers.clear();
+        self.pointer.reset();
+        self.high_surrogate = None;
+    }
+
+    fn release_buttons(&mut self, events: &mut Vec<InputEvent>) {
+        let (x, y) = self.position;
+        for button in self.buttons.drain(..) {
+            events.push(InputEvent::Button {
+                            button: button,
+                            x: x,
+                            y: y,
+                            down: false,
+                        });
+        }
+    }
+
+    pub fn decode(&mut self, wnd: winapi::HWND, msg: winapi::UINT, wparam: winapi::WPARAM, lparam: winapi::LPARAM, events: &mut Vec<InputEvent>) {
+        let first = events.len();
+        match msg {
+            winapi::WM_KEYDOWN |
+            winapi::WM_KEYUP |
+            winapi::WM_SYSKEYDOWN |
+            winapi::WM_SYSKEYUP => {
+                let down = ((lparam >> 31) & 1) == 0;
+                if let Some(vk) = modifier_key(wparam as i32, lparam) {
+                    self.held_modifiers.retain(|&held| held != vk);
+                    if down {
+                        self.held_modifiers.push(vk);
+                    }
+                }
+                let modifiers = self.modifiers();
+                self.decode_key(wparam as i32, down, modifiers, events);
+            }
+            winapi::WM_XBUTTONDOWN |
+            winapi::WM_XBUTTONDBLCLK |
+            winapi::WM_XBUTTONUP => {
+                let down = msg != winapi::WM_XBUTTONUP;
+                let (button, vk) = if ((wparam >> 16) as winapi::DWORD & winapi::XBUTTON2) != 0 {
+                    (XButton::Forward, winapi::VK_XBUTTON2)
+                } else {
+                    (XButton::Back, winapi::VK_XBUTTON1)
+                };
+                events.push(InputEvent::XButton {
+                                button: button,
+                                down: down,
+                            });
+                // the keymap may turn them into nuklear keys as well, VK_XBUTTON1/2 have no bindings by default
+                let modifiers = self.modifiers();
+                self.decode_key(vk, down, modifiers, events);
+            }
+            winapi::WM_CHAR => {
+                // WM_CHAR carries UTF-16 code units, characters outside the BMP come in as two messages
+                let unit = wparam as u32;
+                if unit >= 0xD800 && unit <= 0xDBFF {
+                    self.high_surrogate = Some(unit as u16);
+                } else if unit >= 0xDC00 && unit <= 0xDFFF {
+                    if let Some(high) = self.high_surrogate.take() {
+                        push_char(0x10000 + ((high as u32 - 0xD800) << 10) + (unit - 0xDC00), events);
+                    }
+                } else {
+                    self.high_surrogate = None;
+                    push_char(unit, events);
+                }
+            }
+            winapi::WM_UNICHAR => {
+                if wparam != winapi::UNICODE_NOCHAR {
+                    push_char(wparam as u32, events);
+                }
+            }
+            winapi::WM_MOUSEWHEEL => {
+                self.wheel_y += (wparam >> 16) as u16 as i16 as i32;
+                let lines = take_lines(&mut self.wheel_y, self.scroll_lines);
+                if lines != 0.0 {
+                    events.push(InputEvent::Scroll { x: 0.0, y: lines });
+                }
+            }
+            winapi::WM_MOUSEHWHEEL => {
+                self.wheel_x += (wparam >> 16) as u16 as i16 as i32;
+                let lines = take_lines(&mut self.wheel_x, self.scroll_lines);
+                if lines != 0.0 {
+                    // tilting right is positive here, nuklear scrolls right on negative deltas
+                    events.push(InputEvent::Scroll { x: -lines, y: 0.0 });
+                }
+            }
+            winapi::WM_KILLFOCUS => {
+                self.release_all(events);
+                events.push(InputEvent::Focus(false));
+            }
+            // the buttons that are still down will not see their up messages once another window holds the capture
+            winapi::WM_CAPTURECHANGED => {
+                if lparam as winapi::HWND != wnd {
+                    self.release_buttons(events);
+                }
+            }
+            winapi::WM_MOUSELEAVE => {
+                // a drag keeps following the pointer through the capture
+                if self.buttons.is_empty() {
+                    events.push(InputEvent::Motion {
+                                    x: MOUSE_OUTSIDE,
+                                    y: MOUSE_OUTSIDE,
+                                });
+                }
+            }
+            _ => decode_stateless(msg, lparam, events),
+        }
+        self.track(&events[first..]);
+    }
+
+    // Pointer messages carry screen coordinates, x and y must already be mapped to the client area.
+    pub fn decode_pointer(&mut self, msg: winapi::UINT, wparam: winapi::WPARAM, x: i32, y: i32, events: &mut Vec<InputEvent>) {
+        let first = events.len();
+        self.pointer.decode(msg, wparam, x, y, events);
+        self.track(&events[first..]);
+    }
+
+    fn track(&mut self, events: &[InputEvent]) {
+        for event in events {
+            match *event {
+                InputEvent::Button { button, x, y, down } => {
+                    self.position = (x, y);
+                    self.buttons.retain(|&b| b != button);
+                    if down {
+                        self.buttons.push(button);
+                    }
+                }
+                InputEvent::Motion { x, y } => self.position = (x, y),
+                _ => {}
+            }
+        }
+    }
+
+    fn decode_key(&mut self, vk: i32, down: bool, modifiers: Modifiers, events: &mut Vec<InputEvent>) {
+        // releases go to whatever the press was mapped to, even if the modifiers changed in between
+        let previous = self.pressed.iter().position(|&(pressed, _)| pressed == vk).map(|position| self.pressed.remove(position).1);
+        let keys = match previous {
+            Some(ref keys) if !down => keys.clone(),
+            _ => self.resolve(vk, modifiers),
+        };
+
+        if down {
+            // an auto-repeat after a modifier change may map to different keys, the old ones must not stay down
+            for key in previous.unwrap_or_default() {
+                if !keys.contains(&key) {
+                    events.push(InputEvent::Key {
+                                    key: key,
+                                    down: false,
+                                });
+                }
+            }
+            if !keys.is_empty() {
+                self.pressed.push((vk, keys.clone()));
+            }
+        }
+
+        for key in keys {
+            events.push(InputEvent::Key {
+                            key: key,
+                            down: down,
+                        });
+        }
+    }
+
+    fn resolve(&self, vk: i32, modifiers: Modifiers) -> Vec<NkKey> {
+        match vk {
+            winapi::VK_SHIFT |
+            winapi::VK_LSHIFT |
+            winapi::VK_RSHIFT => vec![NkKey::NK_KEY_SHIFT],
+            winapi::VK_CONTROL |
+            winapi::VK_LCONTROL |
+            winapi::VK_RCONTROL => vec![NkKey::NK_KEY_CTRL],
+            _ => self.keymap.lookup(vk, modifiers).map(|keys| keys.to_vec()).unwrap_or_default(),
+        }
+    }
+}
+
+// Key messages only carry VK_SHIFT, VK_CONTROL and VK_MENU, the side comes from the scan code or the extended-key bit.
+fn modifier_key(vk: i32, lparam: winapi::LPARAM) -> Option<i32> {
+    let extended = ((lparam >> 24) & 1) != 0;
+    match vk {
+        winapi::VK_SHIFT => Some(if ((lparam >> 16) & 0xFF) == 0x36 { winapi::VK_RSHIFT } else { winapi::VK_LSHIFT }),
+        winapi::VK_CONTROL => Some(if extended { winapi::VK_RCONTROL } else { winapi::VK_LCONTROL }),
+        winapi::VK_MENU => Some(if extended { winapi::VK_RMENU } else { winapi::VK_LMENU }),
+        winapi::VK_LSHIFT |
+        winapi::VK_RSHIFT |
+        winapi::VK_LCONTROL |
+        winapi::VK_RCONTROL |
+        winapi::VK_LMENU |
+        winapi::VK_RMENU |
+        winapi::VK_LWIN |
+        winapi::VK_RWIN => Some(vk),
+        _ => None,
+    }
+}
+
+// Precision touchpads report fractions of a notch; whole lines are handed out and the rest is kept for the next message.
+fn take_lines(accumulated: &mut i32, scroll_lines: f32) -> f32 {
+    let lines = (*accumulated as f32 * scroll_lines / winapi::WHEEL_DELTA as f32).trunc();
+    if lines != 0.0 {
+        *accumulated -= (lines * winapi::WHEEL_DELTA as f32 / scroll_lines) as i32;
+    }
+    lines
+}
+
+fn decode_stateless(msg: winapi::UINT, lparam: winapi::LPARAM, events: &mut Vec<InputEvent>) {
+    match msg {
+        winapi::WM_SIZE => {
+            events.push(InputEvent::Resize {
+                            width: lparam as u16,
+                            height: (lparam >> 16) as u16,
+                        });
+        }
+        winapi::WM_SETFOCUS => events.push(InputEvent::Focus(true)),
+        winapi::WM_LBUTTONDOWN => events.push(button(NkButton::NK_BUTTON_LEFT, lparam, true)),
+        winapi::WM_RBUTTONDOWN => events.push(button(NkButton::NK_BUTTON_RIGHT, lparam, true)),
+        winapi::WM_MBUTTONDOWN => events.push(button(NkButton::NK_BUTTON_MIDDLE, lparam, true)),
+        // with CS_DBLCLKS the second press arrives only as a double-click message
+        winapi::WM_LBUTTONDBLCLK => double_click(NkButton::NK_BUTTON_LEFT, lparam, events),
+        winapi::WM_RBUTTONDBLCLK => double_click(NkButton::NK_BUTTON_RIGHT, lparam, events),
+        winapi::WM_MBUTTONDBLCLK => double_click(NkButton::NK_BUTTON_MIDDLE, lparam, events),
+        winapi::WM_LBUTTONUP => release(NkButton::NK_BUTTON_LEFT, lparam, events),
+        winapi::WM_RBUTTONUP => release(NkButton::NK_BUTTON_RIGHT, lparam, events),
+        winapi::WM_MBUTTONUP => release(NkButton::NK_BUTTON_MIDDLE, lparam, events),
+        winapi::WM_MOUSEMOVE => {
+            let (x, y) = point(lparam);
+            events.push(InputEvent::Motion { x: x, y: y });
+        }
+        _ => {}
+    }
+}
+
+fn push_char(codepoint: u32, events: &mut Vec<InputEvent>) {
+    if codepoint >= 32 {
+        if let Some(c) = ::std::char::from_u32(codepoint) {
+            events.push(InputEvent::Char(c));
+        }
+    }
+}
+
+// Client coordinates are signed: they go negative while the mouse is captured and dragged past the left or top edge.
+fn point(lparam: winapi::LPARAM) -> (i32, i32) {
+    (lparam as u16 as i16 as i32, (lparam >> 16) as u16 as i16 as i32)
+}
+
+fn button(button: NkButton, lparam: winapi::LPARAM, down: bool) -> InputEvent {
+    let (x, y) = point(lparam);
+    InputEvent::Button {
+        button: button,
+        x: x,
+        y: y,
+        down: down,
+    }
+}
+
+fn double_click(pressed: NkButton, lparam: winapi::LPARAM, events: &mut Vec<InputEvent>) {
+    events.push(button(pressed, lparam, true));
+    events.push(button(NkButton::NK_BUTTON_DOUBLE, lparam, true));
+}
+
+fn release(released: NkButton, lparam: winapi::LPARAM, events: &mut Vec<InputEvent>) {
+    events.push(button(NkButton::NK_BUTTON_DOUBLE, lparam, false));
+    events.push(button(released, lparam, false));
+}
+
+pub fn apply_event(ctx: &mut NkContext, event: &InputEvent) -> bool {
+    match *event {
+        InputEvent::Key { key, down } => ctx.input_key(key, down),
+        InputEvent::Char(c) => ctx.input_unicode(c),
+        InputEvent::Button { button, x, y, down } => ctx.input_button(button, x, y, down),
+        InputEvent::Motion { x, y } => ctx.input_motion(x, y),
+        // nuklear-rust 0.3 scrolls vertically only, Drawer hands horizontal deltas to its horizontal scroll handler
+        InputEvent::Scroll { y, .. } if y != 0.0 => ctx.input_scroll(y),
+        InputEvent::Scroll { .. } |
+        InputEvent::XButton { .. } |
+        InputEvent::Resize { .. } |
+        InputEvent::Focus(_) => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_UP: winapi::LPARAM = 0x8000_0000;
+    // stands in for the decoding window's handle, which messages only ever compare against
+    const WINDOW: usize = 0x1000;
+
+    fn decode(decoder: &mut InputDecoder, msg: winapi::UINT, wparam: winapi::WPARAM, lparam: winapi::LPARAM) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        decoder.decode(WINDOW as winapi::HWND, msg, wparam, lparam, &mut events);
+        events
+    }
+
+    fn key(key: NkKey, down: bool) -> InputEvent {
+        InputEvent::Key {
+            key: key,
+            down: down,
+        }
+    }
+
+    fn lparam(x: i32, y: i32) -> winapi::LPARAM {
+        (x as u16 as u32 | ((y as u16 as u32) << 16)) as winapi::LPARAM
+    }
+
+    #[test]
+    fn keys_go_through_the_keymap() {
+        let mut decoder = InputDecoder::new();
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, winapi::VK_DELETE as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_DEL, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYUP, winapi::VK_DELETE as winapi::WPARAM, KEY_UP), vec![key(NkKey::NK_KEY_DEL, false)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, b'Q' as winapi::WPARAM, 0), vec![]);
+
+        decoder.keymap_mut().bind(b'Q' as i32, Modifiers::default(), &[NkKey::NK_KEY_ENTER]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, b'Q' as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_ENTER, true)]);
+    }
+
+    #[test]
+    fn modifier_keys_are_reported_directly() {
+        let mut decoder = InputDecoder::new();
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, winapi::VK_CONTROL as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_CTRL, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, b'C' as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_COPY, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYUP, winapi::VK_CONTROL as winapi::WPARAM, KEY_UP), vec![key(NkKey::NK_KEY_CTRL, false)]);
+    }
+
+    #[test]
+    fn window_messages() {
+        let mut decoder = InputDecoder::new();
+        assert_eq!(decode(&mut decoder, winapi::WM_SIZE, 0, lparam(640, 480)), vec![InputEvent::Resize { width: 640, height: 480 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_SETFOCUS, 0, 0), vec![InputEvent::Focus(true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KILLFOCUS, 0, 0), vec![InputEvent::Focus(false)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEMOVE, 0, lparam(3, 4)), vec![InputEvent::Motion { x: 3, y: 4 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_PAINT, 0, 0), vec![]);
+    }
+
+    fn button_event(button: NkButton, down: bool) -> InputEvent {
+        InputEvent::Button {
+            button: button,
+            x: 10,
+            y: 20,
+            down: down,
+        }
+    }
+
+    #[test]
+    fn click_then_double_click() {
+        let cases = [(NkButton::NK_BUTTON_LEFT, winapi::WM_LBUTTONDOWN, winapi::WM_LBUTTONUP, winapi::WM_LBUTTONDBLCLK),
+                     (NkButton::NK_BUTTON_RIGHT, winapi::WM_RBUTTONDOWN, winapi::WM_RBUTTONUP, winapi::WM_RBUTTONDBLCLK),
+                     (NkButton::NK_BUTTON_MIDDLE, winapi::WM_MBUTTONDOWN, winapi::WM_MBUTTONUP, winapi::WM_MBUTTONDBLCLK)];
+        for &(button, down, up, double) in cases.iter() {
+            let mut decoder = InputDecoder::new();
+            let at = lparam(10, 20);
+            assert_eq!(decode(&mut decoder, down, 0, at), vec![button_event(button, true)]);
+            assert_eq!(decode(&mut decoder, up, 0, at), vec![button_event(NkButton::NK_BUTTON_DOUBLE, false), button_event(button, false)]);
+            assert_eq!(decode(&mut decoder, double, 0, at), vec![button_event(button, true), button_event(NkButton::NK_BUTTON_DOUBLE, true)]);
+            assert_eq!(decode(&mut decoder, up, 0, at), vec![button_event(NkButton::NK_BUTTON_DOUBLE, false), button_event(button, false)]);
+            assert!(!decoder.any_button_down());
+        }
+    }
+
+    #[test]
+    fn wheel_lines() {
+        let mut decoder = InputDecoder::new();
+        decoder.set_scroll_lines(0.0);
+        decoder.set_scroll_lines(-3.0);
+        assert_eq!(decoder.scroll_lines(), 1.0);
+
+        decoder.set_scroll_lines(3.0);
+        let notch_back = ((-(winapi::WHEEL_DELTA as i32) as u16 as u32) << 16) as winapi::WPARAM;
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, notch_back, 0), vec![InputEvent::Scroll { x: 0.0, y: -3.0 }]);
+        // a precision touchpad sends a third of a notch at a time
+        decoder.set_scroll_lines(1.0);
+        let third = ((winapi::WHEEL_DELTA as u32 / 3) << 16) as winapi::WPARAM;
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, third, 0), vec![]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, third, 0), vec![]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, third, 0), vec![InputEvent::Scroll { x: 0.0, y: 1.0 }]);
+    }
+
+    #[test]
+    fn coordinates_are_signed() {
+        let mut decoder = InputDecoder::new();
+        let above_left = (-5i16 as u16 as u32 | ((-20i16 as u16 as u32) << 16)) as winapi::LPARAM;
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEMOVE, 0, above_left), vec![InputEvent::Motion { x: -5, y: -20 }]);
+
+        // dragged onto a monitor left of and below the primary one, then onto a wide one to the right
+        assert_eq!(decode(&mut decoder, winapi::WM_LBUTTONDOWN, 0, lparam(-1920, 1079)),
+                   vec![InputEvent::Button {
+                            button: NkButton::NK_BUTTON_LEFT,
+                            x: -1920,
+                            y: 1079,
+                            down: true,
+                        }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEMOVE, 0, lparam(-3839, 2159)), vec![InputEvent::Motion { x: -3839, y: 2159 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEMOVE, 0, lparam(7679, -1)), vec![InputEvent::Motion { x: 7679, y: -1 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_LBUTTONUP, 0, lparam(7679, -1))[1],
+                   InputEvent::Button {
+                       button: NkButton::NK_BUTTON_LEFT,
+                       x: 7679,
+                       y: -1,
+                       down: false,
+                   });
+    }
+
+    fn chars(decoder: &mut InputDecoder, msg: winapi::UINT, units: &[u32]) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        for &unit in units {
+            decoder.decode(WINDOW as winapi::HWND, msg, unit as winapi::WPARAM, 0, &mut events);
+        }
+        events
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        let mut decoder = InputDecoder::new();
+        // U+1F600 arrives as two WM_CHAR messages
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0xD83D, 0xDE00]), vec![InputEvent::Char('\u{1F600}')]);
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0xDE00]), vec![]);
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0xD83D, 'a' as u32]), vec![InputEvent::Char('a')]);
+        // the dropped high surrogate must not pair with a later low one
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0xDE00, 0x00E9]), vec![InputEvent::Char('\u{E9}')]);
+    }
+
+    #[test]
+    fn control_characters_and_unichar() {
+        let mut decoder = InputDecoder::new();
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0x08, 0x0D, 0x1B, 0x1F]), vec![]);
+        assert_eq!(chars(&mut decoder, winapi::WM_CHAR, &[0x20]), vec![InputEvent::Char(' ')]);
+        assert_eq!(chars(&mut decoder, winapi::WM_UNICHAR, &[winapi::UNICODE_NOCHAR as u32]), vec![]);
+        assert_eq!(chars(&mut decoder, winapi::WM_UNICHAR, &[0x1F600, 0x09]), vec![InputEvent::Char('\u{1F600}')]);
+    }
+
+    #[test]
+    fn focus_loss_releases_keys_and_buttons() {
+        let mut decoder = InputDecoder::new();
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, winapi::VK_SHIFT as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_SHIFT, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_LBUTTONDOWN, 0, lparam(10, 20)), vec![button_event(NkButton::NK_BUTTON_LEFT, true)]);
+        assert!(decoder.modifiers().shift);
+
+        assert_eq!(decode(&mut decoder, winapi::WM_KILLFOCUS, 0, 0),
+                   vec![key(NkKey::NK_KEY_SHIFT, false), button_event(NkButton::NK_BUTTON_LEFT, false), InputEvent::Focus(false)]);
+        assert!(!decoder.modifiers().shift);
+        assert!(!decoder.any_button_down());
+    }
+
+    #[test]
+    fn capture_loss_releases_only_buttons() {
+        let mut decoder = InputDecoder::new();
+        decode(&mut decoder, winapi::WM_KEYDOWN, winapi::VK_CONTROL as winapi::WPARAM, 0);
+        decode(&mut decoder, winapi::WM_RBUTTONDOWN, 0, lparam(10, 20));
+
+        assert_eq!(decode(&mut decoder, winapi::WM_CAPTURECHANGED, 0, 0x2000), vec![button_event(NkButton::NK_BUTTON_RIGHT, false)]);
+        assert!(!decoder.any_button_down());
+        assert!(decoder.modifiers().ctrl);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYUP, winapi::VK_CONTROL as winapi::WPARAM, KEY_UP), vec![key(NkKey::NK_KEY_CTRL, false)]);
+    }
+
+    #[test]
+    fn auto_repeat_follows_modifier_changes() {
+        let mut decoder = InputDecoder::new();
+        let left = winapi::VK_LEFT as winapi::WPARAM;
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, left, 0), vec![key(NkKey::NK_KEY_LEFT, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, winapi::VK_CONTROL as winapi::WPARAM, 0), vec![key(NkKey::NK_KEY_CTRL, true)]);
+
+        let repeat = 1 << 30;
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, left, repeat), vec![key(NkKey::NK_KEY_LEFT, false), key(NkKey::NK_KEY_TEXT_WORD_LEFT, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYDOWN, left, repeat), vec![key(NkKey::NK_KEY_TEXT_WORD_LEFT, true)]);
+        assert_eq!(decode(&mut decoder, winapi::WM_KEYUP, left, KEY_UP), vec![key(NkKey::NK_KEY_TEXT_WORD_LEFT, false)]);
+    }
+
+    #[test]
+    fn horizontal_wheel() {
+        let mut decoder = InputDecoder::new();
+        decoder.set_scroll_lines(1.0);
+        let notch_right = ((winapi::WHEEL_DELTA as u32) << 16) as winapi::WPARAM;
+        let notch_left = ((-(winapi::WHEEL_DELTA as i32) as u16 as u32) << 16) as winapi::WPARAM;
+        // tilting right reports a positive delta, nuklear's convention wants a negative one
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEHWHEEL, notch_right, 0), vec![InputEvent::Scroll { x: -1.0, y: 0.0 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEHWHEEL, notch_left, 0), vec![InputEvent::Scroll { x: 1.0, y: 0.0 }]);
+
+        // the two axes accumulate separately
+        let half = ((winapi::WHEEL_DELTA as u32 / 2) << 16) as winapi::WPARAM;
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEHWHEEL, half, 0), vec![]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, half, 0), vec![]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEHWHEEL, half, 0), vec![InputEvent::Scroll { x: -1.0, y: 0.0 }]);
+        assert_eq!(decode(&mut decoder, winapi::WM_MOUSEWHEEL, half, 0), vec![InputEvent::Scroll { x: 0.0, y: 1.0 }]);
+    }
+
+    #[test]
+    fn chorded_buttons_keep_the_capture() {
+        let mut decoder = InputDecoder::new();
+        decode(&mut decoder, winapi::WM_LBUTTONDOWN, 0, lparam(10, 20));
+        decode(&mut decoder, winapi::WM_RBUTTONDOWN, 0, lparam(10, 20));
+        // SetCapture on the window that already holds the capture still reports a change, to the window itself
+        assert_eq!(decode(&mut decoder, winapi::WM_CAPTURECHANGED, 0, WINDOW as winapi::LPARAM), vec![]);
+        assert!(decoder.any_button_down());
+
+        assert_eq!(decode(&mut decoder, winapi::WM_RBUTTONUP, 0, lparam(10, 20)),
+                   vec![button_event(NkButton::NK_BUTTON_DOUBLE, false), button_event(NkButton::NK_BUTTON_RIGHT, false)]);
+        assert!(decoder.any_button_down());
+        assert_eq!(decode(&mut decoder, winapi::WM_CAPTURECHANGED, 0, 0),
+                   vec![button_event(NkButton::NK_BUTTON_LEFT, false)]);
+        assert!(!decoder.any_button_down());
+    }
+}
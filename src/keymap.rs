@@ -0,0 +1,189 @@
+use super::nuklear_rust::NkKey;
+
+use super::winapi;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub win: bool,
+}
+
+const NONE: Modifiers = Modifiers {
+    ctrl: false,
+    shift: false,
+    alt: false,
+    win: false,
+};
+const CTRL: Modifiers = Modifiers {
+    ctrl: true,
+    shift: false,
+    alt: false,
+    win: false,
+};
+const SHIFT: Modifiers = Modifiers {
+    ctrl: false,
+    shift: true,
+    alt: false,
+    win: false,
+};
+const ALT: Modifiers = Modifiers {
+    ctrl: false,
+    shift: false,
+    alt: true,
+    win: false,
+};
+const CTRL_SHIFT: Modifiers = Modifiers {
+    ctrl: true,
+    shift: true,
+    alt: false,
+    win: false,
+};
+
+const DEFAULT_BINDINGS: &'static [(i32, Modifiers, &'static [NkKey])] = &[
+    (winapi::VK_DELETE, NONE, &[NkKey::NK_KEY_DEL]),
+    (winapi::VK_RETURN, NONE, &[NkKey::NK_KEY_ENTER]),
+    (winapi::VK_TAB, NONE, &[NkKey::NK_KEY_TAB]),
+    (winapi::VK_BACK, NONE, &[NkKey::NK_KEY_BACKSPACE]),
+    (winapi::VK_LEFT, NONE, &[NkKey::NK_KEY_LEFT]),
+    (winapi::VK_RIGHT, NONE, &[NkKey::NK_KEY_RIGHT]),
+    (winapi::VK_UP, NONE, &[NkKey::NK_KEY_UP]),
+    (winapi::VK_DOWN, NONE, &[NkKey::NK_KEY_DOWN]),
+    (winapi::VK_LEFT, CTRL, &[NkKey::NK_KEY_TEXT_WORD_LEFT]),
+    (winapi::VK_RIGHT, CTRL, &[NkKey::NK_KEY_TEXT_WORD_RIGHT]),
+    (winapi::VK_HOME, NONE, &[NkKey::NK_KEY_TEXT_LINE_START]),
+    (winapi::VK_END, NONE, &[NkKey::NK_KEY_TEXT_LINE_END]),
+    (winapi::VK_HOME, CTRL, &[NkKey::NK_KEY_TEXT_START, NkKey::NK_KEY_SCROLL_START]),
+    (winapi::VK_END, CTRL, &[NkKey::NK_KEY_TEXT_END, NkKey::NK_KEY_SCROLL_END]),
+    (winapi::VK_NEXT, NONE, &[NkKey::NK_KEY_SCROLL_DOWN]),
+    (winapi::VK_PRIOR, NONE, &[NkKey::NK_KEY_SCROLL_UP]),
+    (b'A' as i32, CTRL, &[NkKey::NK_KEY_TEXT_SELECT_ALL]),
+    (b'C' as i32, CTRL, &[NkKey::NK_KEY_COPY]),
+    (b'V' as i32, CTRL, &[NkKey::NK_KEY_PASTE]),
+    (b'X' as i32, CTRL, &[NkKey::NK_KEY_CUT]),
+    (b'Z' as i32, CTRL, &[NkKey::NK_KEY_TEXT_UNDO]),
+    (b'Y' as i32, CTRL, &[NkKey::NK_KEY_TEXT_REDO]),
+    (b'Z' as i32, CTRL_SHIFT, &[NkKey::NK_KEY_TEXT_REDO]),
+    (winapi::VK_INSERT, CTRL, &[NkKey::NK_KEY_COPY]),
+    (winapi::VK_INSERT, SHIFT, &[NkKey::NK_KEY_PASTE]),
+    (winapi::VK_DELETE, SHIFT, &[NkKey::NK_KEY_CUT]),
+    // edit modes only matter for edits without NK_EDIT_ALWAYS_INSERT_MODE, nuklear switches them out of view mode only
+    (winapi::VK_INSERT, NONE, &[NkKey::NK_KEY_TEXT_INSERT_MODE]),
+    (winapi::VK_INSERT, ALT, &[NkKey::NK_KEY_TEXT_REPLACE_MODE]),
+    (winapi::VK_ESCAPE, NONE, &[NkKey::NK_KEY_TEXT_RESET_MODE]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<(i32, Modifiers), Vec<NkKey>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut keymap = Keymap::empty();
+        for &(vk, modifiers, keys) in DEFAULT_BINDINGS {
+            keymap.bind(vk, modifiers, keys);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn empty() -> Keymap {
+        Keymap { bindings: HashMap::new() }
+    }
+
+    pub fn bind(&mut self, vk: i32, modifiers: Modifiers, keys: &[NkKey]) {
+        self.bindings.insert((vk, modifiers), keys.to_vec());
+    }
+
+    pub fn unbind(&mut self, vk: i32, modifiers: Modifiers) {
+        self.bindings.remove(&(vk, modifiers));
+    }
+
+    // Shift is left to nuklear (NK_KEY_SHIFT extends the selection), so a combo without its own Shift binding falls back to the one without Shift.
+    pub fn lookup(&self, vk: i32, modifiers: Modifiers) -> Option<&[NkKey]> {
+        self.bindings
+            .get(&(vk, modifiers))
+            .or_else(|| if modifiers.shift {
+                         self.bindings.get(&(vk, Modifiers { shift: false, ..modifiers }))
+                     } else {
+                         None
+                     })
+            .map(|keys| keys.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::input::{InputDecoder, InputEvent};
+
+    // Every nuklear key with a combo that produces it; Shift and Ctrl come from the decoder rather than the keymap.
+    const EVERY_KEY: &'static [(NkKey, i32, Modifiers)] = &[
+        (NkKey::NK_KEY_SHIFT, winapi::VK_SHIFT, NONE),
+        (NkKey::NK_KEY_CTRL, winapi::VK_CONTROL, NONE),
+        (NkKey::NK_KEY_DEL, winapi::VK_DELETE, NONE),
+        (NkKey::NK_KEY_ENTER, winapi::VK_RETURN, NONE),
+        (NkKey::NK_KEY_TAB, winapi::VK_TAB, NONE),
+        (NkKey::NK_KEY_BACKSPACE, winapi::VK_BACK, NONE),
+        (NkKey::NK_KEY_COPY, b'C' as i32, CTRL),
+        (NkKey::NK_KEY_CUT, b'X' as i32, CTRL),
+        (NkKey::NK_KEY_PASTE, b'V' as i32, CTRL),
+        (NkKey::NK_KEY_UP, winapi::VK_UP, NONE),
+        (NkKey::NK_KEY_DOWN, winapi::VK_DOWN, NONE),
+        (NkKey::NK_KEY_LEFT, winapi::VK_LEFT, NONE),
+        (NkKey::NK_KEY_RIGHT, winapi::VK_RIGHT, NONE),
+        (NkKey::NK_KEY_TEXT_INSERT_MODE, winapi::VK_INSERT, NONE),
+        (NkKey::NK_KEY_TEXT_REPLACE_MODE, winapi::VK_INSERT, ALT),
+        (NkKey::NK_KEY_TEXT_RESET_MODE, winapi::VK_ESCAPE, NONE),
+        (NkKey::NK_KEY_TEXT_LINE_START, winapi::VK_HOME, NONE),
+        (NkKey::NK_KEY_TEXT_LINE_END, winapi::VK_END, NONE),
+        (NkKey::NK_KEY_TEXT_START, winapi::VK_HOME, CTRL),
+        (NkKey::NK_KEY_TEXT_END, winapi::VK_END, CTRL),
+        (NkKey::NK_KEY_TEXT_UNDO, b'Z' as i32, CTRL),
+        (NkKey::NK_KEY_TEXT_REDO, b'Y' as i32, CTRL),
+        (NkKey::NK_KEY_TEXT_SELECT_ALL, b'A' as i32, CTRL),
+        (NkKey::NK_KEY_TEXT_WORD_LEFT, winapi::VK_LEFT, CTRL),
+        (NkKey::NK_KEY_TEXT_WORD_RIGHT, winapi::VK_RIGHT, CTRL),
+        (NkKey::NK_KEY_SCROLL_START, winapi::VK_HOME, CTRL),
+        (NkKey::NK_KEY_SCROLL_END, winapi::VK_END, CTRL),
+        (NkKey::NK_KEY_SCROLL_DOWN, winapi::VK_NEXT, NONE),
+        (NkKey::NK_KEY_SCROLL_UP, winapi::VK_PRIOR, NONE),
+    ];
+
+    #[test]
+    fn every_key_is_listed() {
+        let mut listed = EVERY_KEY.iter().map(|&(key, _, _)| key as i32).collect::<Vec<_>>();
+        listed.sort();
+        listed.dedup();
+        assert_eq!(listed, (NkKey::NK_KEY_NONE as i32 + 1..NkKey::NK_KEY_MAX as i32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn every_key_has_a_binding() {
+        let keymap = Keymap::default();
+        for &(key, vk, modifiers) in EVERY_KEY {
+            let bound = match key {
+                NkKey::NK_KEY_SHIFT | NkKey::NK_KEY_CTRL => {
+                    let mut events = Vec::new();
+                    InputDecoder::new().decode(::std::ptr::null_mut(), winapi::WM_KEYDOWN, vk as winapi::WPARAM, 0, &mut events);
+                    events == vec![InputEvent::Key { key: key, down: true }]
+                }
+                _ => keymap.lookup(vk, modifiers).map_or(false, |keys| keys.contains(&key)),
+            };
+            assert!(bound, "{:?} is not produced by {:#x} with {:?}", key, vk, modifiers);
+        }
+    }
+
+    #[test]
+    fn shift_falls_back_to_the_unshifted_binding() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.lookup(winapi::VK_HOME, CTRL_SHIFT), Some(&[NkKey::NK_KEY_TEXT_START, NkKey::NK_KEY_SCROLL_START][..]));
+        assert_eq!(keymap.lookup(b'Z' as i32, CTRL_SHIFT), Some(&[NkKey::NK_KEY_TEXT_REDO][..]));
+        assert_eq!(keymap.lookup(b'Q' as i32, SHIFT), None);
+    }
+}
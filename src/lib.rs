@@ -1,5 +1,3 @@
-#![cfg_attr(feature = "own_window", feature(drop_types_in_const))]
-
 extern crate nuklear_rust;
 
 extern crate winapi;
@@ -9,84 +7,411 @@ extern crate user32;
 
 #[cfg(feature = "piston_image")]
 extern crate image;
-#[cfg(feature = "own_window")]
+#[cfg(all(windows, feature = "own_window"))]
 mod own_window;
+#[cfg(all(windows, feature = "own_window"))]
+mod window_manager;
+mod input;
+mod keymap;
+mod ime;
+mod record;
+mod pointer;
 
 use nuklear_rust::*;
 use nuklear_rust::nuklear_sys as nksys;
 use std::{ptr, mem, str, slice, ffi};
+use std::cell::{Cell, RefCell};
 use std::os::raw;
+use std::io;
+use std::path::Path;
+
+pub use input::{InputEvent, InputDecoder, XButton, MOUSE_OUTSIDE, apply_event};
+pub use keymap::{Keymap, Modifiers};
+pub use ime::PreeditHandler;
+pub use record::{InputRecorder, InputReplayer, RecordedFrame};
+#[cfg(all(windows, feature = "own_window"))]
+pub use own_window::MessageHandler;
+#[cfg(all(windows, feature = "own_window"))]
+pub use window_manager::{WindowManager, WindowID};
 
 pub type FontID = usize;
 
+const GGO_METRICS: winapi::UINT = 0;
+const GDI_ERROR: winapi::DWORD = 0xFFFFFFFF;
+const GGI_MARK_NONEXISTING_GLYPHS: winapi::DWORD = 1;
+const COLORONCOLOR: i32 = 3;
+
+#[allow(non_camel_case_types)]
+type SCRIPT_CACHE = *mut raw::c_void;
+
+// Only linked on Windows, like imm32 in the ime module.
+#[cfg_attr(windows, link(name = "usp10"))]
+extern "system" {
+    fn ScriptGetCMap(dc: winapi::HDC, cache: *mut SCRIPT_CACHE, chars: *const u16, count: i32, flags: winapi::DWORD, glyphs: *mut u16) -> winapi::HRESULT;
+    fn ScriptFreeCache(cache: *mut SCRIPT_CACHE) -> winapi::HRESULT;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontMetrics {
+    pub height: i32,
+    pub ascent: i32,
+    pub descent: i32,
+    pub internal_leading: i32,
+    pub external_leading: i32,
+    pub average_char_width: i32,
+    pub max_char_width: i32,
+}
+
+impl FontMetrics {
+    pub fn line_height(&self) -> i32 {
+        self.height + self.external_leading
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontQuality {
+    NonAntialiased,
+    Grayscale,
+    ClearType,
+}
+
+impl FontQuality {
+    fn to_gdi(self) -> winapi::DWORD {
+        match self {
+            FontQuality::NonAntialiased => winapi::NONANTIALIASED_QUALITY,
+            FontQuality::Grayscale => winapi::ANTIALIASED_QUALITY,
+            FontQuality::ClearType => winapi::CLEARTYPE_QUALITY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemFont {
+    Message,
+    Caption,
+    SmallCaption,
+    Menu,
+    Status,
+}
+
+// How process_events waits for messages before draining everything that is pending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventMode {
+    Block,
+    Wait(u32),
+    Poll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemCursor {
+    Arrow,
+    IBeam,
+    Move,
+    SizeNS,
+    SizeWE,
+    SizeNWSE,
+    SizeNESW,
+    Hand,
+    Wait,
+}
+
+// In the order of nk_style_cursor, which indexes the style's cursor table.
+const STYLE_CURSORS: [SystemCursor; 7] = [SystemCursor::Arrow, SystemCursor::IBeam, SystemCursor::Move, SystemCursor::SizeNS, SystemCursor::SizeWE, SystemCursor::SizeNWSE, SystemCursor::SizeNESW];
+
+impl SystemCursor {
+    fn load(self) -> winapi::HCURSOR {
+        let id = match self {
+            SystemCursor::Arrow => winapi::IDC_ARROW,
+            SystemCursor::IBeam => winapi::IDC_IBEAM,
+            SystemCursor::Move => winapi::IDC_SIZEALL,
+            SystemCursor::SizeNS => winapi::IDC_SIZENS,
+            SystemCursor::SizeWE => winapi::IDC_SIZEWE,
+            SystemCursor::SizeNWSE => winapi::IDC_SIZENWSE,
+            SystemCursor::SizeNESW => winapi::IDC_SIZENESW,
+            SystemCursor::Hand => winapi::IDC_HAND,
+            SystemCursor::Wait => winapi::IDC_WAIT,
+        };
+        unsafe { user32::LoadCursorW(ptr::null_mut(), id) }
+    }
+}
+
 struct GdiFont {
     nk: nksys::nk_user_font,
-    height: i32,
+    metrics: FontMetrics,
+    name: String,
+    size: i32,
+    quality: FontQuality,
+    quality_override: Option<FontQuality>,
+    system: Option<SystemFont>,
     handle: winapi::HFONT,
     dc: winapi::HDC,
+    fallbacks: Vec<GdiFont>,
 }
 
 impl GdiFont {
-    pub unsafe fn new(name: &str, size: i32) -> GdiFont {
-        let mut metric = winapi::TEXTMETRICW {
-            tmHeight: 0,
-            tmAscent: 0,
-            tmDescent: 0,
-            tmInternalLeading: 0,
-            tmExternalLeading: 0,
-            tmAveCharWidth: 0,
-            tmMaxCharWidth: 0,
-            tmWeight: 0,
-            tmOverhang: 0,
-            tmDigitizedAspectX: 0,
-            tmDigitizedAspectY: 0,
-            tmFirstChar: 0,
-            tmLastChar: 0,
-            tmDefaultChar: 0,
-            tmBreakChar: 0,
-            tmItalic: 0,
-            tmUnderlined: 0,
-            tmStruckOut: 0,
-            tmPitchAndFamily: 0,
-            tmCharSet: 0,
-        };
-        let handle = gdi32::CreateFontA(size,
-                                        0,
-                                        0,
-                                        0,
-                                        winapi::FW_NORMAL,
-                                        winapi::FALSE as u32,
-                                        winapi::FALSE as u32,
-                                        winapi::FALSE as u32,
-                                        winapi::DEFAULT_CHARSET,
-                                        winapi::OUT_DEFAULT_PRECIS,
-                                        winapi::CLIP_DEFAULT_PRECIS,
-                                        winapi::CLEARTYPE_QUALITY,
-                                        winapi::DEFAULT_PITCH | winapi::FF_DONTCARE,
-                                        name.as_ptr() as *const i8);
+    pub unsafe fn new(name: &str, size: i32, quality: FontQuality) -> GdiFont {
+        let handle = create_font_handle(name, size, quality);
         let dc = gdi32::CreateCompatibleDC(ptr::null_mut());
 
         gdi32::SelectObject(dc, handle as *mut raw::c_void);
-        gdi32::GetTextMetricsW(dc, &mut metric);
 
         GdiFont {
             nk: mem::uninitialized(),
-            height: metric.tmHeight,
-            handle: handle as winapi::HFONT,
+            metrics: font_metrics(dc),
+            name: name.to_owned(),
+            size: size,
+            quality: quality,
+            quality_override: None,
+            system: None,
+            handle: handle,
             dc: dc,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    unsafe fn set_face(&mut self, name: &str, size: i32) {
+        let handle = create_font_handle(name, size, self.quality);
+        gdi32::SelectObject(self.dc, handle as *mut raw::c_void);
+        gdi32::DeleteObject(self.handle as *mut raw::c_void);
+        self.handle = handle;
+        self.name = name.to_owned();
+        self.size = size;
+        self.metrics = font_metrics(self.dc);
+        self.nk.height = self.metrics.height as f32;
+        // fallbacks keep their own faces but follow the primary's size
+        for fallback in self.fallbacks.iter_mut() {
+            let name = fallback.name.clone();
+            fallback.set_face(&name, size);
         }
     }
+
+    unsafe fn set_quality(&mut self, quality: FontQuality) {
+        if self.quality != quality {
+            let handle = create_font_handle(&self.name, self.size, quality);
+            gdi32::SelectObject(self.dc, handle as *mut raw::c_void);
+            gdi32::DeleteObject(self.handle as *mut raw::c_void);
+            self.handle = handle;
+            self.quality = quality;
+        }
+        for fallback in self.fallbacks.iter_mut() {
+            fallback.set_quality(quality);
+        }
+    }
+
+    fn face(&self, index: usize) -> &GdiFont {
+        if index == 0 {
+            self
+        } else {
+            &self.fallbacks[index - 1]
+        }
+    }
+
+    unsafe fn covers(&self, wstr: &[u16]) -> Vec<bool> {
+        let mut indices = vec![0u16; wstr.len()];
+        gdi32::GetGlyphIndicesW(self.dc,
+                                wstr.as_ptr(),
+                                wstr.len() as i32,
+                                indices.as_mut_ptr(),
+                                GGI_MARK_NONEXISTING_GLYPHS);
+        indices.into_iter().map(|i| i != 0xFFFF).collect()
+    }
+
+    // GetGlyphIndicesW maps single UTF-16 units only, Uniscribe also maps the surrogate pairs of characters outside the BMP.
+    unsafe fn covers_pair(&self, pair: &[u16]) -> bool {
+        let mut cache = ptr::null_mut();
+        let mut glyphs = [0u16; 2];
+        let result = ScriptGetCMap(self.dc, &mut cache, pair.as_ptr(), pair.len() as i32, 0, glyphs.as_mut_ptr());
+        ScriptFreeCache(&mut cache);
+        // S_FALSE when the font lacks the character
+        result == 0
+    }
+
+    unsafe fn pair_face(&self, pair: &[u16]) -> usize {
+        (0..self.fallbacks.len() + 1)
+            .position(|index| self.face(index).covers_pair(pair))
+            .unwrap_or(0)
+    }
+
+    unsafe fn face_for_char(&self, codepoint: u32) -> &GdiFont {
+        if self.fallbacks.is_empty() {
+            return self;
+        }
+        if codepoint > 0xFFFF {
+            let c = codepoint - 0x10000;
+            let pair = [(0xD800 + (c >> 10)) as u16, (0xDC00 + (c & 0x3FF)) as u16];
+            return self.face(self.pair_face(&pair));
+        }
+        let wstr = [codepoint as u16];
+        for index in 0..self.fallbacks.len() + 1 {
+            let face = self.face(index);
+            if face.covers(&wstr)[0] {
+                return face;
+            }
+        }
+        self
+    }
+
+    // Splits a wide string into (face, start, end) runs, so that each run is drawn with the first face of the chain that has its glyphs.
+    unsafe fn runs(&self, wstr: &[u16]) -> Vec<(usize, usize, usize)> {
+        if self.fallbacks.is_empty() || wstr.is_empty() {
+            return vec![(0, 0, wstr.len())];
+        }
+
+        let coverage = (0..self.fallbacks.len() + 1)
+            .map(|index| self.face(index).covers(wstr))
+            .collect::<Vec<_>>();
+
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, &unit) in wstr.iter().enumerate() {
+            let face = if is_high_surrogate(unit) && wstr.get(i + 1).map_or(false, |&next| is_low_surrogate(next)) {
+                self.pair_face(&wstr[i..i + 2])
+            } else if is_high_surrogate(unit) || is_low_surrogate(unit) {
+                // the low half belongs to the pair's run, unpaired halves have no glyph of their own
+                runs.last().map(|r| r.0).unwrap_or(0)
+            } else {
+                coverage
+                    .iter()
+                    .position(|c| c[i])
+                    .unwrap_or(0)
+            };
+
+            match runs.last_mut() {
+                Some(run) if run.0 == face => run.2 = i + 1,
+                _ => runs.push((face, i, i + 1)),
+            }
+        }
+        runs
+    }
+
+    unsafe fn text_width(&self, wstr: &[u16]) -> Option<i32> {
+        let mut width = 0;
+        for (face, start, end) in self.runs(wstr) {
+            let mut size = winapi::SIZE { cx: 0, cy: 0 };
+            if gdi32::GetTextExtentPoint32W(self.face(face).dc,
+                                            wstr[start..].as_ptr(),
+                                            (end - start) as i32,
+                                            &mut size) <= 0 {
+                return None;
+            }
+            width += size.cx;
+        }
+        Some(width)
+    }
+}
+
+fn is_high_surrogate(unit: u16) -> bool {
+    unit >= 0xD800 && unit <= 0xDBFF
+}
+
+fn is_low_surrogate(unit: u16) -> bool {
+    unit >= 0xDC00 && unit <= 0xDFFF
+}
+
+unsafe fn font_metrics(dc: winapi::HDC) -> FontMetrics {
+    let mut metric = winapi::TEXTMETRICW {
+        tmHeight: 0,
+        tmAscent: 0,
+        tmDescent: 0,
+        tmInternalLeading: 0,
+        tmExternalLeading: 0,
+        tmAveCharWidth: 0,
+        tmMaxCharWidth: 0,
+        tmWeight: 0,
+        tmOverhang: 0,
+        tmDigitizedAspectX: 0,
+        tmDigitizedAspectY: 0,
+        tmFirstChar: 0,
+        tmLastChar: 0,
+        tmDefaultChar: 0,
+        tmBreakChar: 0,
+        tmItalic: 0,
+        tmUnderlined: 0,
+        tmStruckOut: 0,
+        tmPitchAndFamily: 0,
+        tmCharSet: 0,
+    };
+    gdi32::GetTextMetricsW(dc, &mut metric);
+
+    FontMetrics {
+        height: metric.tmHeight,
+        ascent: metric.tmAscent,
+        descent: metric.tmDescent,
+        internal_leading: metric.tmInternalLeading,
+        external_leading: metric.tmExternalLeading,
+        average_char_width: metric.tmAveCharWidth,
+        max_char_width: metric.tmMaxCharWidth,
+    }
+}
+
+unsafe fn system_font_face(font: SystemFont, dpi: i32) -> Option<(String, i32)> {
+    let mut metrics: winapi::NONCLIENTMETRICSW = mem::zeroed();
+    metrics.cbSize = mem::size_of::<winapi::NONCLIENTMETRICSW>() as winapi::UINT;
+    if user32::SystemParametersInfoW(winapi::SPI_GETNONCLIENTMETRICS,
+                                     metrics.cbSize,
+                                     &mut metrics as *mut _ as *mut raw::c_void,
+                                     0) == 0 {
+        return None;
+    }
+
+    let logfont = match font {
+        SystemFont::Message => metrics.lfMessageFont,
+        SystemFont::Caption => metrics.lfCaptionFont,
+        SystemFont::SmallCaption => metrics.lfSmCaptionFont,
+        SystemFont::Menu => metrics.lfMenuFont,
+        SystemFont::Status => metrics.lfStatusFont,
+    };
+    let name_len = logfont
+        .lfFaceName
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(logfont.lfFaceName.len());
+    let name = String::from_utf16_lossy(&logfont.lfFaceName[..name_len]);
+
+    // the metrics are given for the system DPI, the surface may be on a monitor with another one
+    let screen = user32::GetDC(ptr::null_mut());
+    let system_dpi = gdi32::GetDeviceCaps(screen, winapi::LOGPIXELSY);
+    user32::ReleaseDC(ptr::null_mut(), screen);
+    let size = if system_dpi > 0 {
+        (logfont.lfHeight as i64 * dpi as i64 / system_dpi as i64) as i32
+    } else {
+        logfont.lfHeight
+    };
+
+    Some((name, size))
+}
+
+unsafe fn create_font_handle(name: &str, size: i32, quality: FontQuality) -> winapi::HFONT {
+    // encode_utf16 rather than OsStrExt keeps the crate root buildable off Windows for the decoder tests
+    let wname = name.encode_utf16().chain(Some(0).into_iter()).collect::<Vec<_>>();
+    gdi32::CreateFontW(size,
+                       0,
+                       0,
+                       0,
+                       winapi::FW_NORMAL,
+                       winapi::FALSE as u32,
+                       winapi::FALSE as u32,
+                       winapi::FALSE as u32,
+                       winapi::DEFAULT_CHARSET,
+                       winapi::OUT_DEFAULT_PRECIS,
+                       winapi::CLIP_DEFAULT_PRECIS,
+                       quality.to_gdi(),
+                       winapi::DEFAULT_PITCH | winapi::FF_DONTCARE,
+                       wname.as_ptr()) as winapi::HFONT
 }
 
 impl Drop for GdiFont {
     fn drop(&mut self) {
         unsafe {
-            gdi32::DeleteObject(self.handle as *mut raw::c_void);
+            // the DC goes first, the font is still selected into it
             gdi32::DeleteDC(self.dc);
+            gdi32::DeleteObject(self.handle as *mut raw::c_void);
         }
     }
 }
 
+// Source bitmap, source region, cursor size and hotspot.
+type CursorKey = (winapi::HBITMAP, [u16; 4], i32, i32, i32, i32);
+
 pub struct Drawer {
     bitmap: winapi::HBITMAP,
     window_dc: winapi::HDC,
@@ -94,6 +419,21 @@ pub struct Drawer {
     width: i32,
     height: i32,
     fonts: Vec<GdiFont>,
+    font_quality: FontQuality,
+    dpi: i32,
+    input: InputDecoder,
+    ime: ime::Ime,
+    xbutton_handler: Option<Box<FnMut(XButton, bool)>>,
+    horizontal_scroll_handler: Option<Box<FnMut(f32)>>,
+    recorder: Option<InputRecorder>,
+    style_cursors: Box<[nksys::nk_cursor; 7]>,
+    custom_cursors: RefCell<Vec<(CursorKey, winapi::HCURSOR)>>,
+    cursor_override: Option<SystemCursor>,
+    cursor: Cell<winapi::HCURSOR>,
+    cursor_in_client: bool,
+    grabbed: bool,
+    tracking_mouse: bool,
+    pen_pressure: Option<f32>,
 
     window: Option<winapi::HWND>,
 }
@@ -108,6 +448,21 @@ impl Drawer {
                 width: width as i32,
                 height: height as i32,
                 fonts: Vec::new(),
+                font_quality: FontQuality::ClearType,
+                dpi: gdi32::GetDeviceCaps(window_dc, winapi::LOGPIXELSY),
+                input: InputDecoder::new(),
+                ime: ime::Ime::new(),
+                xbutton_handler: None,
+                horizontal_scroll_handler: None,
+                recorder: None,
+                style_cursors: Box::new(mem::zeroed()),
+                custom_cursors: RefCell::new(Vec::new()),
+                cursor_override: None,
+                cursor: Cell::new(SystemCursor::Arrow.load()),
+                cursor_in_client: false,
+                grabbed: false,
+                tracking_mouse: false,
+                pen_pressure: None,
 
                 window: window,
             };
@@ -122,36 +477,193 @@ impl Drawer {
             let mut context: &mut nksys::nk_context = mem::transmute(context);
             context.clip.copy = Some(nk_gdi_clipbard_copy);
             context.clip.paste = Some(nk_gdi_clipbard_paste);
+
+            // placeholders that only tell which cursor nuklear picked, the system draws the real one
+            for (slot, cursor) in context.style.cursors.iter_mut().zip(self.style_cursors.iter()) {
+                *slot = cursor;
+            }
+            context.style.cursor_active = &self.style_cursors[0];
+            context.style.cursor_visible = 0;
         }
     }
 
+    pub fn set_cursor_override(&mut self, cursor: Option<SystemCursor>) {
+        self.cursor_override = cursor;
+    }
+
     pub fn window(&self) -> Option<winapi::HWND> {
         self.window
     }
 
-    pub fn process_events(&mut self, ctx: &mut NkContext) -> bool {
-        unsafe {
-            let mut msg: winapi::MSG = mem::zeroed();
-            ctx.input_begin();
+    // Client coordinates of the focused edit's caret, e.g. the edit's bounds plus the width of the text before the cursor
+    // measured with the edit's font. The app sets it while an edit is active and resets it when none is; until then the
+    // IME windows open at the last left click.
+    pub fn set_ime_caret(&mut self, x: i32, y: i32, height: i32) {
+        self.ime.set_caret(Some((x, y, height)));
+    }
 
-            if user32::GetMessageW(&mut msg, ptr::null_mut(), 0, 0) <= 0 {
-                return false;
-            } else {
-                user32::TranslateMessage(&mut msg);
-                user32::DispatchMessageW(&mut msg);
+    pub fn reset_ime_caret(&mut self) {
+        self.ime.set_caret(None);
+    }
+
+    pub fn ime_composition(&self) -> Option<&str> {
+        self.ime.composition()
+    }
+
+    // With a handler installed the app draws the composition string itself and the system composition window stays hidden.
+    pub fn set_preedit_handler(&mut self, handler: Option<PreeditHandler>) {
+        #[cfg(all(windows, feature = "own_window"))]
+        {
+            if let Some(wnd) = self.window {
+                own_window::set_inline_ime(wnd, handler.is_some());
             }
+        }
+        self.ime.set_preedit_handler(handler);
+    }
 
-            #[cfg(feature = "own_window")]
-            own_window::process_events(self, ctx);
+    // Window procedures of their own pass WM_IME_SETCONTEXT's lparam through this before calling DefWindowProcW.
+    pub fn ime_context_lparam(&self, lparam: winapi::LPARAM) -> winapi::LPARAM {
+        if self.ime.draws_inline() {
+            lparam & !ime::ISC_SHOWUICOMPOSITIONWINDOW
+        } else {
+            lparam
+        }
+    }
+
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.recorder = Some(InputRecorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_xbutton_handler(&mut self, handler: Option<Box<FnMut(XButton, bool)>>) {
+        self.xbutton_handler = handler;
+    }
+
+    // nuklear only takes vertical scrolling, so horizontal wheel and pan deltas go here, in lines and signed like nuklear's
+    // own deltas: positive moves the view left.
+    pub fn set_horizontal_scroll_handler(&mut self, handler: Option<Box<FnMut(f32)>>) {
+        self.horizontal_scroll_handler = handler;
+    }
 
-            ctx.input_end();
-            return true;
+    #[cfg(all(windows, feature = "own_window"))]
+    pub fn set_message_handler(&mut self, handler: Option<MessageHandler>) {
+        if let Some(wnd) = self.window {
+            own_window::set_message_handler(wnd, handler);
         }
     }
 
+    // Pressure of the pen in contact or hovering, from 0 to 1.
+    pub fn pen_pressure(&self) -> Option<f32> {
+        self.pen_pressure
+    }
+
+    pub fn mouse_emulation(&self) -> bool {
+        self.input.mouse_emulation()
+    }
+
+    pub fn set_mouse_emulation(&mut self, enabled: bool) {
+        #[cfg(all(windows, feature = "own_window"))]
+        {
+            if let Some(wnd) = self.window {
+                own_window::set_mouse_emulation(wnd, enabled);
+            }
+        }
+        self.input.set_mouse_emulation(enabled);
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.input.modifiers()
+    }
+
+    pub fn input_decoder(&self) -> &InputDecoder {
+        &self.input
+    }
+
+    pub fn input_decoder_mut(&mut self) -> &mut InputDecoder {
+        &mut self.input
+    }
+
+    pub fn process_events(&mut self, ctx: &mut NkContext) -> bool {
+        self.process_events_with(ctx, EventMode::Block)
+    }
+
+    pub fn process_events_with(&mut self, ctx: &mut NkContext, mode: EventMode) -> bool {
+        self.begin_input_frame(ctx);
+        let running = unsafe {
+            pump_messages(mode,
+                          &mut || {
+                #[cfg(all(windows, feature = "own_window"))]
+                own_window::process_events(self, ctx);
+            })
+        };
+        if !running {
+            return false;
+        }
+        self.end_input_frame(ctx);
+        true
+    }
+
+    // Loops of their own call these around handle_event instead of process_events, so recording and mouse grabs keep working.
+    pub fn begin_input_frame(&mut self, ctx: &mut NkContext) {
+        ctx.input_begin();
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.begin_frame();
+        }
+        unsafe {
+            self.update_grab(ctx);
+        }
+    }
+
+    pub fn end_input_frame(&mut self, ctx: &mut NkContext) {
+        unsafe {
+            self.hold_grabbed_cursor(ctx);
+        }
+        ctx.input_end();
+    }
+
     pub fn new_font(&mut self, name: &str, size: u16) -> FontID {
-        self.fonts
-            .push(unsafe { GdiFont::new(name, size as i32) });
+        self.new_font_with_fallbacks(name, size, &[])
+    }
+
+    pub fn new_font_with_fallbacks(&mut self, name: &str, size: u16, fallbacks: &[&str]) -> FontID {
+        let quality = self.effective_font_quality(self.font_quality);
+        let mut font = unsafe { GdiFont::new(name, size as i32, quality) };
+        font.fallbacks = fallbacks
+            .iter()
+            .map(|name| unsafe { GdiFont::new(name, size as i32, quality) })
+            .collect();
+        self.push_font(font)
+    }
+
+    pub fn new_system_font(&mut self, system: SystemFont) -> FontID {
+        self.new_system_font_with_fallbacks(system, &[])
+    }
+
+    // The fallbacks follow the system font's size through settings and DPI changes.
+    pub fn new_system_font_with_fallbacks(&mut self, system: SystemFont, fallbacks: &[&str]) -> FontID {
+        let quality = self.effective_font_quality(self.font_quality);
+        let mut font = unsafe {
+            let (name, size) = system_font_face(system, self.dpi).unwrap_or_else(|| ("Segoe UI".to_owned(), -12));
+            let mut font = GdiFont::new(&name, size, quality);
+            font.fallbacks = fallbacks
+                .iter()
+                .map(|name| GdiFont::new(name, size, quality))
+                .collect();
+            font
+        };
+        font.system = Some(system);
+        self.push_font(font)
+    }
+
+    fn push_font(&mut self, font: GdiFont) -> FontID {
+        self.fonts.push(font);
 
         let index = self.fonts.len() - 1;
         let mut gdifont = &mut self.fonts[index];
@@ -160,14 +672,15 @@ impl Drawer {
             ptr::write(&mut gdifont.nk,
                        nksys::nk_user_font {
                            userdata: nksys::nk_handle_ptr(gdifont as *mut _ as *mut raw::c_void),
-                           height: gdifont.height as f32,
+                           height: gdifont.metrics.height as f32,
                            width: None,
                            query: None,
                            texture: nksys::nk_handle::default(),
                        });
 
-            gdifont.nk.height = gdifont.height as f32;
+            gdifont.nk.height = gdifont.metrics.height as f32;
             gdifont.nk.width = Some(nk_gdifont_get_text_width);
+            gdifont.nk.query = Some(nk_gdifont_query_font_glyph);
         }
 
         index as FontID
@@ -181,6 +694,65 @@ impl Drawer {
         Some(unsafe { ::std::mem::transmute(&self.fonts.get(id).unwrap().nk) })
     }
 
+    pub fn font_metrics(&self, id: FontID) -> Option<FontMetrics> {
+        self.fonts.get(id).map(|font| font.metrics)
+    }
+
+    pub fn font_quality(&self) -> FontQuality {
+        self.font_quality
+    }
+
+    pub fn set_font_quality(&mut self, quality: FontQuality) {
+        self.font_quality = quality;
+        self.refresh_font_quality();
+    }
+
+    pub fn set_font_quality_for(&mut self, id: FontID, quality: Option<FontQuality>) {
+        if let Some(font) = self.fonts.get_mut(id) {
+            font.quality_override = quality;
+        }
+        self.refresh_font_quality();
+    }
+
+    fn refresh_system_fonts(&mut self) {
+        for font in self.fonts.iter_mut() {
+            if let Some(system) = font.system {
+                unsafe {
+                    if let Some((name, size)) = system_font_face(system, self.dpi) {
+                        font.set_face(&name, size);
+                    }
+                }
+            }
+        }
+    }
+
+    fn has_alpha_surface(&self) -> bool {
+        match self.window {
+            Some(window) => unsafe { (user32::GetWindowLongW(window, winapi::GWL_EXSTYLE) as winapi::DWORD & winapi::WS_EX_LAYERED) != 0 },
+            None => false,
+        }
+    }
+
+    fn effective_font_quality(&self, quality: FontQuality) -> FontQuality {
+        // ClearType subpixel colour fringes turn into visible halos once the surface is alpha-composited
+        if quality == FontQuality::ClearType && self.has_alpha_surface() {
+            FontQuality::Grayscale
+        } else {
+            quality
+        }
+    }
+
+    fn refresh_font_quality(&mut self) {
+        let default = self.font_quality;
+        for index in 0..self.fonts.len() {
+            let requested = self.fonts[index].quality_override.unwrap_or(default);
+            let quality = self.effective_font_quality(requested);
+            unsafe {
+                self.fonts[index].set_quality(quality);
+            }
+        }
+    }
+
     #[cfg(feature = "piston_image")]
     pub fn add_image(&mut self, img: &image::DynamicImage) -> NkHandle {
         use image::{Pixel, GenericImage};
@@ -241,18 +813,54 @@ impl Drawer {
     }
 
     pub fn handle_event(&mut self, ctx: &mut NkContext, wnd: winapi::HWND, msg: winapi::UINT, wparam: winapi::WPARAM, lparam: winapi::LPARAM) -> bool {
+        let mut events = Vec::new();
+        let mut consumed = false;
         match msg {
-            winapi::WM_SIZE => {
-                let width = lparam as u16;
-                let height = (lparam >> 16) as u16;
-                if width as i32 != self.width || height as i32 != self.height {
+            winapi::WM_STYLECHANGED => {
+                self.refresh_font_quality();
+            }
+            winapi::WM_SETCURSOR => {
+                self.cursor_in_client = lparam as u16 as i16 as i32 == winapi::HTCLIENT;
+                if self.cursor_in_client {
                     unsafe {
-                        gdi32::DeleteObject(self.bitmap as *mut raw::c_void);
-                        self.bitmap = gdi32::CreateCompatibleBitmap(self.window_dc, width as i32, height as i32);
-                        self.width = width as i32;
-                        self.height = height as i32;
-                        gdi32::SelectObject(self.memory_dc, self.bitmap as *mut raw::c_void);
+                        user32::SetCursor(self.cursor.get());
                     }
+                    return true;
+                }
+            }
+            winapi::WM_MOUSEMOVE => {
+                // WM_MOUSELEAVE is sent once per request, so tracking is renewed on the first move after it
+                if !self.tracking_mouse {
+                    let mut track = winapi::TRACKMOUSEEVENT {
+                        cbSize: mem::size_of::<winapi::TRACKMOUSEEVENT>() as winapi::DWORD,
+                        dwFlags: winapi::TME_LEAVE,
+                        hwndTrack: wnd,
+                        dwHoverTime: 0,
+                    };
+                    self.tracking_mouse = unsafe { user32::TrackMouseEvent(&mut track) != 0 };
+                }
+            }
+            winapi::WM_MOUSELEAVE => {
+                self.tracking_mouse = false;
+                self.cursor_in_client = false;
+            }
+            winapi::WM_SETTINGCHANGE => {
+                if wparam as winapi::UINT == winapi::SPI_SETNONCLIENTMETRICS {
+                    self.refresh_system_fonts();
+                }
+            }
+            winapi::WM_DPICHANGED => {
+                self.dpi = (wparam & 0xffff) as i32;
+                self.refresh_system_fonts();
+            }
+            winapi::WM_IME_STARTCOMPOSITION |
+            winapi::WM_IME_COMPOSITION |
+            winapi::WM_IME_ENDCOMPOSITION => {
+                let committed = unsafe { self.ime.handle_message(wnd, msg, lparam) };
+                if self.ime.draws_inline() {
+                    // returning true keeps the system composition window away, so the committed text is delivered here
+                    events.extend(committed.chars().filter(|&c| c >= ' ').map(InputEvent::Char));
+                    consumed = true;
                 }
             }
             winapi::WM_PAINT => {
@@ -264,186 +872,215 @@ impl Drawer {
                 }
                 return true;
             }
-            winapi::WM_KEYDOWN |
-            winapi::WM_KEYUP |
-            winapi::WM_SYSKEYDOWN |
-            winapi::WM_SYSKEYUP => {
-                let down = ((lparam >> 31) & 1) == 0;
-                let ctrl = unsafe { (user32::GetKeyState(winapi::VK_CONTROL) & (1 << 15)) != 0 };
-
-                match wparam as i32 {
-                    winapi::VK_SHIFT |
-                    winapi::VK_LSHIFT |
-                    winapi::VK_RSHIFT => {
-                        ctx.input_key(NkKey::NK_KEY_SHIFT, down);
-                        return true;
-                    }
-                    winapi::VK_DELETE => {
-                        ctx.input_key(NkKey::NK_KEY_DEL, down);
-                        return true;
-                    }
-                    winapi::VK_RETURN => {
-                        ctx.input_key(NkKey::NK_KEY_ENTER, down);
-                        return true;
-                    }
-                    winapi::VK_TAB => {
-                        ctx.input_key(NkKey::NK_KEY_TAB, down);
-                        return true;
-                    }
-                    winapi::VK_LEFT => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_TEXT_WORD_LEFT, down);
-                        } else {
-                            ctx.input_key(NkKey::NK_KEY_LEFT, down);
-                        }
-                        return true;
+            _ => {}
+        }
+
+        if pointer::is_pointer_message(msg) {
+            let id = wparam as u16 as u32;
+            let mut point = winapi::POINT {
+                x: lparam as u16 as i16 as i32,
+                y: (lparam >> 16) as u16 as i16 as i32,
+            };
+            unsafe {
+                user32::ScreenToClient(wnd, &mut point);
+                self.pen_pressure = match msg {
+                    winapi::WM_POINTERLEAVE | winapi::WM_POINTERCAPTURECHANGED => None,
+                    _ => pointer::pen_pressure(id),
+                };
+            }
+            self.input.decode_pointer(msg, wparam, point.x, point.y, &mut events);
+            // keeps DefWindowProc from synthesizing the mouse messages a second time
+            consumed = !self.input.mouse_emulation();
+        } else {
+            if !self.input.mouse_emulation() && unsafe { pointer::is_emulated_mouse_message(msg) } {
+                return true;
+            }
+            self.input.decode(wnd, msg, wparam, lparam, &mut events);
+        }
+
+        let mut released_outside = false;
+        for event in events {
+            match event {
+                InputEvent::Resize { width, height } => self.resize(width, height),
+                InputEvent::Focus(false) => unsafe {
+                    self.set_grab(false);
+                },
+                InputEvent::Button { button, x, y, down: true } => {
+                    if button == NkButton::NK_BUTTON_LEFT {
+                        self.ime.set_last_click(x, y);
                     }
-                    winapi::VK_RIGHT => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_TEXT_WORD_RIGHT, down);
-                        } else {
-                            ctx.input_key(NkKey::NK_KEY_RIGHT, down);
+                    // the second button of a chord or a double click must not take the capture again
+                    unsafe {
+                        if user32::GetCapture() != wnd {
+                            user32::SetCapture(wnd);
                         }
-                        return true;
                     }
-                    winapi::VK_BACK => {
-                        ctx.input_key(NkKey::NK_KEY_BACKSPACE, down);
-                        return true;
-                    }
-                    winapi::VK_HOME => {
-                        ctx.input_key(NkKey::NK_KEY_TEXT_START, down);
-                        ctx.input_key(NkKey::NK_KEY_SCROLL_START, down);
-                        return true;
-                    }
-                    winapi::VK_END => {
-                        ctx.input_key(NkKey::NK_KEY_TEXT_END, down);
-                        ctx.input_key(NkKey::NK_KEY_SCROLL_END, down);
-                        return true;
-                    }
-                    winapi::VK_NEXT => {
-                        ctx.input_key(NkKey::NK_KEY_SCROLL_DOWN, down);
-                        return true;
-                    }
-                    winapi::VK_PRIOR => {
-                        ctx.input_key(NkKey::NK_KEY_SCROLL_UP, down);
-                        return true;
-                    }
-                    _ => {}
                 }
-                match wparam as u8 as char {
-                    'C' => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_COPY, down);
-                            return true;
-                        }
-                    }		
-                    'V' => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_PASTE, down);
-                            return true;
-                        }
-                    }
-                    'X' => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_CUT, down);
-                            return true;
-                        }
-                    }
-                    'Z' => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_TEXT_UNDO, down);
-                            return true;
-                        }
-                    }   
-                    'R' => {
-                        if ctrl {
-                            ctx.input_key(NkKey::NK_KEY_TEXT_REDO, down);
-                            return true;
-                        }
+                InputEvent::Button { down: false, .. } if !self.input.any_button_down() => unsafe {
+                    user32::ReleaseCapture();
+                    // WM_MOUSELEAVE was ignored while the button was held, a drag that ended outside must not leave the pointer hovering
+                    released_outside = !in_client_area(wnd);
+                },
+                InputEvent::XButton { button, down } => {
+                    if let Some(ref mut handler) = self.xbutton_handler {
+                        handler(button, down);
+                        consumed = true;
                     }
-                    _ => {}
                 }
-            }
-            winapi::WM_CHAR => {
-                if wparam >= 32 {
-                    unsafe { ctx.input_unicode(::std::char::from_u32_unchecked(wparam as u32)); }
-                    return true;
-                }
-            }
-            winapi::WM_LBUTTONDOWN => {
-                ctx.input_button(NkButton::NK_BUTTON_LEFT,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 true);
-                unsafe {
-                    user32::SetCapture(wnd);
+                InputEvent::Scroll { x, .. } if x != 0.0 => {
+                    if let Some(ref mut handler) = self.horizontal_scroll_handler {
+                        handler(x);
+                        consumed = true;
+                    }
                 }
-                return true;
+                _ => {}
             }
-            winapi::WM_LBUTTONUP => {
-                ctx.input_button(NkButton::NK_BUTTON_LEFT,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 false);
-                unsafe {
-                    user32::ReleaseCapture();
-                }
-                return true;
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.record(&event);
             }
-            winapi::WM_RBUTTONDOWN => {
-                ctx.input_button(NkButton::NK_BUTTON_RIGHT,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 true);
-                unsafe {
-                    user32::SetCapture(wnd);
-                }
-                return true;
+            consumed |= apply_event(ctx, &event);
+        }
+        if released_outside {
+            self.cursor_in_client = false;
+            let outside = InputEvent::Motion {
+                x: MOUSE_OUTSIDE,
+                y: MOUSE_OUTSIDE,
+            };
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.record(&outside);
             }
-            winapi::WM_RBUTTONUP => {
-                ctx.input_button(NkButton::NK_BUTTON_RIGHT,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 false);
-                unsafe {
-                    user32::ReleaseCapture();
+            apply_event(ctx, &outside);
+        }
+        consumed
+    }
+
+    // nuklear resets cursor_active when the frame is cleared, so it is picked up before rendering
+    fn update_cursor(&self, ctx: &mut NkContext) {
+        let cursor = unsafe {
+            let context: &mut nksys::nk_context = mem::transmute(ctx);
+            // cursors loaded by the application are shown by the system too, nuklear must not draw them again
+            context.style.cursor_visible = 0;
+            let active = context.style.cursor_active;
+            match self.cursor_override {
+                Some(cursor) => cursor.load(),
+                None if active.is_null() => SystemCursor::Arrow.load(),
+                None => {
+                    match self.style_cursors.iter().position(|cursor| cursor as *const _ == active) {
+                        Some(index) => STYLE_CURSORS[index].load(),
+                        None => self.custom_cursor(&*active),
+                    }
                 }
-                return true;
             }
-            winapi::WM_MBUTTONDOWN => {
-                ctx.input_button(NkButton::NK_BUTTON_MIDDLE,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 true);
-                unsafe {
-                    user32::SetCapture(wnd);
-                }
-                return true;
+        };
+        self.cursor.set(cursor);
+        if self.cursor_in_client && self.window.map_or(true, |wnd| unsafe { under_cursor(wnd) }) {
+            unsafe {
+                user32::SetCursor(cursor);
             }
-            winapi::WM_MBUTTONUP => {
-                ctx.input_button(NkButton::NK_BUTTON_MIDDLE,
-                                 lparam as u16 as i32,
-                                 (lparam >> 16) as u16 as i32,
-                                 false);
-                unsafe {
-                    user32::ReleaseCapture();
-                }
-                return true;
+        }
+    }
+
+    // Images from add_image are 32 bit DIB sections, which make color cursors with their alpha channel as is.
+    unsafe fn custom_cursor(&self, cursor: &nksys::nk_cursor) -> winapi::HCURSOR {
+        let bitmap = *cursor.img.handle.ptr.as_ref() as winapi::HBITMAP;
+        // a sub-image of an atlas is cut out of it and scaled to the cursor's size
+        let region = if cursor.img.region == [0; 4] {
+            [0, 0, cursor.img.w, cursor.img.h]
+        } else {
+            cursor.img.region
+        };
+        let (width, height) = if cursor.size.x >= 1.0 && cursor.size.y >= 1.0 {
+            (cursor.size.x as i32, cursor.size.y as i32)
+        } else {
+            (region[2] as i32, region[3] as i32)
+        };
+        let key = (bitmap, region, width, height, cursor.offset.x as i32, cursor.offset.y as i32);
+        if let Some(&(_, handle)) = self.custom_cursors.borrow().iter().find(|&&(k, _)| k == key) {
+            return handle;
+        }
+
+        let color = copy_bitmap_region(bitmap, region, width, height);
+        // an AND mask of zeroes leaves every pixel to the color bitmap and its alpha channel
+        let mask_bits = vec![0u8; (((width + 15) / 16) * 2 * height) as usize];
+        let mask = gdi32::CreateBitmap(width, height, 1, 1, mask_bits.as_ptr() as *const raw::c_void);
+        let mut info = winapi::ICONINFO {
+            fIcon: winapi::FALSE,
+            xHotspot: key.4 as winapi::DWORD,
+            yHotspot: key.5 as winapi::DWORD,
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        let handle = user32::CreateIconIndirect(&mut info);
+        gdi32::DeleteObject(mask as *mut raw::c_void);
+        gdi32::DeleteObject(color as *mut raw::c_void);
+        if handle.is_null() {
+            return SystemCursor::Arrow.load();
+        }
+        self.custom_cursors.borrow_mut().push((key, handle));
+        handle
+    }
+
+    // Properties and knobs grab the mouse while dragged: the cursor is hidden and put back every frame, so only the deltas count and the screen edges never stop a drag.
+    unsafe fn update_grab(&mut self, ctx: &mut NkContext) {
+        let context: &mut nksys::nk_context = mem::transmute(ctx);
+        if context.input.mouse.grab != 0 {
+            self.set_grab(true);
+        } else if context.input.mouse.ungrab != 0 {
+            self.set_grab(false);
+        }
+    }
+
+    unsafe fn set_grab(&mut self, grab: bool) {
+        if grab == self.grabbed {
+            return;
+        }
+        self.grabbed = grab;
+        user32::ShowCursor(!grab as winapi::BOOL);
+        match self.window {
+            Some(wnd) if grab => {
+                let mut rect: winapi::RECT = mem::zeroed();
+                user32::GetClientRect(wnd, &mut rect);
+                user32::MapWindowPoints(wnd, ptr::null_mut(), &mut rect as *mut _ as winapi::LPPOINT, 2);
+                user32::ClipCursor(&rect);
             }
-            winapi::WM_MOUSEWHEEL => {
-                ctx.input_scroll(((wparam >> 16) as u16) as f32 / winapi::WHEEL_DELTA as f32);
-                return true;
+            _ => {
+                user32::ClipCursor(ptr::null());
             }
-            winapi::WM_MOUSEMOVE => {
-                ctx.input_motion(lparam as u16 as i32, (lparam >> 16) as u16 as i32);
-                return true;
+        }
+    }
+
+    unsafe fn hold_grabbed_cursor(&self, ctx: &mut NkContext) {
+        let context: &mut nksys::nk_context = mem::transmute(ctx);
+        if !self.grabbed || context.input.mouse.grabbed == 0 {
+            return;
+        }
+        // the motion of this frame already went into the delta
+        context.input.mouse.pos.x = context.input.mouse.prev.x;
+        context.input.mouse.pos.y = context.input.mouse.prev.y;
+        if let Some(wnd) = self.window {
+            let mut point = winapi::POINT {
+                x: context.input.mouse.prev.x as i32,
+                y: context.input.mouse.prev.y as i32,
+            };
+            user32::ClientToScreen(wnd, &mut point);
+            user32::SetCursorPos(point.x, point.y);
+        }
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        if width as i32 != self.width || height as i32 != self.height {
+            unsafe {
+                gdi32::DeleteObject(self.bitmap as *mut raw::c_void);
+                self.bitmap = gdi32::CreateCompatibleBitmap(self.window_dc, width as i32, height as i32);
+                self.width = width as i32;
+                self.height = height as i32;
+                gdi32::SelectObject(self.memory_dc, self.bitmap as *mut raw::c_void);
             }
-            _ => {}
         }
-        false
     }
 
     pub fn render(&self, ctx: &mut NkContext, clear: NkColor) {
+        self.update_cursor(ctx);
         unsafe {
             let memory_dc = self.memory_dc;
             gdi32::SelectObject(memory_dc, gdi32::GetStockObject(winapi::DC_PEN));
@@ -654,12 +1291,102 @@ impl Drawer {
 impl Drop for Drawer {
     fn drop(&mut self) {
         unsafe {
+            self.set_grab(false);
+            for &(_, cursor) in self.custom_cursors.borrow().iter() {
+                user32::DestroyCursor(cursor);
+            }
             gdi32::DeleteObject(self.memory_dc as *mut raw::c_void);
             gdi32::DeleteObject(self.bitmap as *mut raw::c_void);
         }
     }
 }
 
+// Block waits for and dispatches one message, Wait sleeps until input arrives or the timeout passes and Poll returns at
+// once; all of them then dispatch whatever is pending, calling back after every message. False once WM_QUIT arrives.
+unsafe fn pump_messages(mode: EventMode, dispatched: &mut FnMut()) -> bool {
+    let mut msg: winapi::MSG = mem::zeroed();
+    match mode {
+        EventMode::Block => {
+            if user32::GetMessageW(&mut msg, ptr::null_mut(), 0, 0) <= 0 {
+                return false;
+            }
+            user32::TranslateMessage(&mut msg);
+            user32::DispatchMessageW(&mut msg);
+            dispatched();
+        }
+        EventMode::Wait(timeout) => {
+            // MWMO_INPUTAVAILABLE also wakes for messages that were already in the queue before the wait
+            user32::MsgWaitForMultipleObjectsEx(0, ptr::null(), timeout, winapi::QS_ALLINPUT, winapi::MWMO_INPUTAVAILABLE);
+        }
+        EventMode::Poll => {}
+    }
+
+    while user32::PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, winapi::PM_REMOVE) != 0 {
+        if msg.message == winapi::WM_QUIT {
+            return false;
+        }
+        user32::TranslateMessage(&mut msg);
+        user32::DispatchMessageW(&mut msg);
+        dispatched();
+    }
+    true
+}
+
+unsafe fn under_cursor(wnd: winapi::HWND) -> bool {
+    let mut point = mem::zeroed();
+    user32::GetCursorPos(&mut point) != 0 && user32::WindowFromPoint(point) == wnd
+}
+
+unsafe fn in_client_area(wnd: winapi::HWND) -> bool {
+    let mut point = mem::zeroed();
+    let mut client = mem::zeroed();
+    if user32::GetCursorPos(&mut point) == 0 || user32::WindowFromPoint(point) != wnd {
+        return false;
+    }
+    user32::ScreenToClient(wnd, &mut point);
+    user32::GetClientRect(wnd, &mut client);
+    point.x >= client.left && point.x < client.right && point.y >= client.top && point.y < client.bottom
+}
+
+// Copies the (x, y, w, h) region of a bitmap into a new 32-bit one of the given size, stretching it when the sizes differ.
+unsafe fn copy_bitmap_region(bitmap: winapi::HBITMAP, region: [u16; 4], width: i32, height: i32) -> winapi::HBITMAP {
+    let mut info: winapi::BITMAPINFO = mem::zeroed();
+    info.bmiHeader.biSize = mem::size_of::<winapi::BITMAPINFOHEADER>() as u32;
+    info.bmiHeader.biWidth = width;
+    info.bmiHeader.biHeight = height;
+    info.bmiHeader.biPlanes = 1;
+    info.bmiHeader.biBitCount = 32;
+    info.bmiHeader.biCompression = winapi::BI_RGB;
+
+    let screen = user32::GetDC(ptr::null_mut());
+    let mut bits = ptr::null_mut();
+    let copy = gdi32::CreateDIBSection(screen, &info, winapi::DIB_RGB_COLORS, &mut bits, ptr::null_mut(), 0);
+    let source_dc = gdi32::CreateCompatibleDC(screen);
+    let target_dc = gdi32::CreateCompatibleDC(screen);
+    user32::ReleaseDC(ptr::null_mut(), screen);
+
+    let previous_source = gdi32::SelectObject(source_dc, bitmap as *mut raw::c_void);
+    let previous_target = gdi32::SelectObject(target_dc, copy as *mut raw::c_void);
+    // plain copies of the pixels, alpha included, instead of the default mode's blending of dropped rows
+    gdi32::SetStretchBltMode(target_dc, COLORONCOLOR);
+    gdi32::StretchBlt(target_dc,
+                      0,
+                      0,
+                      width,
+                      height,
+                      source_dc,
+                      region[0] as i32,
+                      region[1] as i32,
+                      region[2] as i32,
+                      region[3] as i32,
+                      winapi::SRCCOPY);
+    gdi32::SelectObject(source_dc, previous_source);
+    gdi32::SelectObject(target_dc, previous_target);
+    gdi32::DeleteDC(source_dc);
+    gdi32::DeleteDC(target_dc);
+    copy
+}
+
 fn convert_color(c: NkColor) -> winapi::COLORREF {
     c.r as u32 | ((c.g as u32) << 8) | ((c.b as u32) << 16)
 }
@@ -964,15 +1691,35 @@ unsafe fn nk_gdi_draw_text(dc: winapi::HDC, x: i32, y: i32, _: i32, _: i32, text
     gdi32::SetBkColor(dc, convert_color(cbg));
     gdi32::SetTextColor(dc, convert_color(cfg));
 
-    gdi32::SelectObject(dc, (*font).handle as *mut raw::c_void);
-    gdi32::ExtTextOutW(dc,
-                       x,
-                       y,
-                       winapi::ETO_OPAQUE,
-                       ptr::null_mut(),
-                       wstr.as_mut_ptr(),
-                       wsize as u32,
-                       ptr::null_mut());
+    let font = &*font;
+    let wstr = &wstr[..wsize as usize];
+    let mut x = x;
+    // the faces are put back afterwards, a font still selected into the DC could not be deleted when it is replaced
+    let mut previous = ptr::null_mut();
+    for (face, start, end) in font.runs(wstr) {
+        let face = font.face(face);
+        let run = &wstr[start..end];
+        let mut size = winapi::SIZE { cx: 0, cy: 0 };
+
+        let replaced = gdi32::SelectObject(dc, face.handle as *mut raw::c_void);
+        if previous.is_null() {
+            previous = replaced;
+        }
+        // text is drawn from the top of the cell, runs of a fallback face are moved onto the primary face's baseline
+        gdi32::ExtTextOutW(dc,
+                           x,
+                           y + font.metrics.ascent - face.metrics.ascent,
+                           winapi::ETO_OPAQUE,
+                           ptr::null_mut(),
+                           run.as_ptr(),
+                           run.len() as u32,
+                           ptr::null_mut());
+        gdi32::GetTextExtentPoint32W(face.dc, run.as_ptr(), run.len() as i32, &mut size);
+        x += size.cx;
+    }
+    if !previous.is_null() {
+        gdi32::SelectObject(dc, previous);
+    }
     gdi32::SetDCBrushColor(dc, convert_color(cbg));
 }
 
@@ -982,7 +1729,6 @@ unsafe extern "C" fn nk_gdifont_get_text_width(handle: nksys::nk_handle, _: f32,
         return 0.0;
     }
 
-    let mut size = winapi::SIZE { cx: 0, cy: 0 };
     let wsize = kernel32::MultiByteToWideChar(winapi::CP_UTF8, 0, text, len, ptr::null_mut(), 0);
     let mut wstr: Vec<winapi::wchar_t> = vec![0; wsize as usize];
     kernel32::MultiByteToWideChar(winapi::CP_UTF8,
@@ -992,13 +1738,71 @@ unsafe extern "C" fn nk_gdifont_get_text_width(handle: nksys::nk_handle, _: f32,
                                   wstr.as_mut_slice() as *mut _ as *mut winapi::wchar_t,
                                   wsize);
 
-    if gdi32::GetTextExtentPoint32W((*font).dc,
-                                    wstr.as_slice() as *const _ as *const winapi::wchar_t,
-                                    wsize,
-                                    &mut size) > 0 {
-        size.cx as f32
-    } else {
-        -1.0
+    match (*font).text_width(wstr.as_slice()) {
+        Some(width) => width as f32,
+        None => -1.0,
+    }
+}
+
+unsafe extern "C" fn nk_gdifont_query_font_glyph(handle: nksys::nk_handle, _: f32, glyph: *mut nksys::nk_user_font_glyph, codepoint: nksys::nk_rune, _: nksys::nk_rune) {
+    let font = *handle.ptr.as_ref() as *const GdiFont;
+    if font.is_null() || glyph.is_null() {
+        return;
+    }
+    let primary = &*font;
+    let font = primary.face_for_char(codepoint);
+    let glyph = &mut *glyph;
+
+    glyph.uv = mem::zeroed();
+    // without an outline the glyph is the whole cell, put onto the primary face's baseline like the drawn runs
+    glyph.offset = nksys::nk_vec2 {
+        x: 0.0,
+        y: (primary.metrics.ascent - font.metrics.ascent) as f32,
+    };
+    glyph.width = 0.0;
+    glyph.height = font.metrics.height as f32;
+    glyph.xadvance = 0.0;
+
+    if codepoint <= 0xFFFF {
+        let identity = winapi::MAT2 {
+            eM11: winapi::FIXED { fract: 0, value: 1 },
+            eM12: winapi::FIXED { fract: 0, value: 0 },
+            eM21: winapi::FIXED { fract: 0, value: 0 },
+            eM22: winapi::FIXED { fract: 0, value: 1 },
+        };
+        let mut metrics: winapi::GLYPHMETRICS = mem::zeroed();
+        if gdi32::GetGlyphOutlineW(font.dc,
+                                   codepoint,
+                                   GGO_METRICS,
+                                   &mut metrics,
+                                   0,
+                                   ptr::null_mut(),
+                                   &identity) != GDI_ERROR {
+            glyph.offset.x = metrics.gmptGlyphOrigin.x as f32;
+            glyph.offset.y = (primary.metrics.ascent - metrics.gmptGlyphOrigin.y) as f32;
+            glyph.width = metrics.gmBlackBoxX as f32;
+            glyph.height = metrics.gmBlackBoxY as f32;
+            glyph.xadvance = metrics.gmCellIncX as f32;
+            return;
+        }
+
+        // raster and vector fonts have no outlines and no ABC spacing, only advance widths
+        let mut width = 0;
+        if gdi32::GetCharWidth32W(font.dc, codepoint, codepoint, &mut width) > 0 {
+            glyph.width = width as f32;
+            glyph.xadvance = width as f32;
+            return;
+        }
+    }
+
+    if let Some(c) = ::std::char::from_u32(codepoint) {
+        let mut wstr = [0u16; 2];
+        let wsize = c.encode_utf16(&mut wstr).len() as i32;
+        let mut size = winapi::SIZE { cx: 0, cy: 0 };
+        if gdi32::GetTextExtentPoint32W(font.dc, wstr.as_ptr(), wsize, &mut size) > 0 {
+            glyph.width = size.cx as f32;
+            glyph.xadvance = size.cx as f32;
+        }
     }
 }
 
@@ -1062,18 +1866,28 @@ unsafe extern "C" fn nk_gdi_clipbard_copy(_: nksys::nk_handle, text: *const i8,
     }
 }
 
-#[cfg(feature = "own_window")]
+#[cfg(all(windows, feature = "own_window"))]
+pub fn bundle_with_system_font(window_name: &str, width: u16, height: u16, system_font: SystemFont, allocator: &mut NkAllocator) -> (Drawer, NkContext, FontID) {
+    let (hwnd, hdc) = own_window::create_env(window_name, width, height);
+    bundle_window(hwnd, hdc, width, height, |drawer| drawer.new_system_font(system_font), allocator)
+}
+
+#[cfg(all(windows, feature = "own_window"))]
 pub fn bundle<'a>(window_name: &str, width: u16, height: u16, font_name: &str, font_size: u16, allocator: &mut NkAllocator) -> (Drawer, NkContext, FontID) {
     let (hwnd, hdc) = own_window::create_env(window_name, width, height);
+    bundle_window(hwnd, hdc, width, height, |drawer| drawer.new_font(font_name, font_size), allocator)
+}
 
+#[cfg(all(windows, feature = "own_window"))]
+fn bundle_window<F: FnOnce(&mut Drawer) -> FontID>(hwnd: winapi::HWND, hdc: winapi::HDC, width: u16, height: u16, font: F, allocator: &mut NkAllocator) -> (Drawer, NkContext, FontID) {
     let mut drawer = Drawer::new(hdc, width, height, Some(hwnd));
 
-    let font_id = drawer.new_font(font_name, font_size);
+    let font_id = font(&mut drawer);
     let mut context = {
         let font = drawer.font_by_id(font_id).unwrap();
         NkContext::new(allocator, &font)
     };
     drawer.install_statics(&mut context);
 
-    (drawer, context, font_id as FontID)
+    (drawer, context, font_id)
 }
@@ -5,30 +5,83 @@ use super::kernel32;
 use super::user32;
 
 use std::{ptr, mem, str};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::os::windows::ffi::OsStrExt;
 use std::ffi::OsStr;
 
 use super::Drawer;
+use super::ime;
+
+pub type MessageHandler = Box<FnMut(winapi::HWND, winapi::UINT, winapi::WPARAM, winapi::LPARAM) -> Option<winapi::LRESULT>>;
+
+// Lives in the window's user data from WM_NCCREATE to WM_NCDESTROY and is only touched from the thread owning the window.
+struct WindowData {
+    events: RefCell<VecDeque<(winapi::UINT, winapi::WPARAM, winapi::LPARAM)>>,
+    handler: RefCell<Option<MessageHandler>>,
+    // managed windows leave closing to the window manager and do not end the message loop when destroyed
+    managed: bool,
+    close_requested: Cell<bool>,
+    // set while the drawer draws the IME composition itself
+    inline_ime: Cell<bool>,
+    // cleared once the drawer takes pointer messages itself, DefWindowProcW would synthesize mouse messages from them
+    mouse_emulation: Cell<bool>,
+}
 
 pub fn create_env(window_name: &str, width: u16, height: u16) -> (winapi::HWND, winapi::HDC) {
+    create_env_with(window_name, width, height, false)
+}
+
+pub fn create_managed_env(window_name: &str, width: u16, height: u16) -> (winapi::HWND, winapi::HDC) {
+    create_env_with(window_name, width, height, true)
+}
+
+fn create_env_with(window_name: &str, width: u16, height: u16, managed: bool) -> (winapi::HWND, winapi::HDC) {
     unsafe {
-        let window = create_window(window_name, width as i32, height as i32);
+        let window = create_window(window_name, width as i32, height as i32, managed);
         let hdc = user32::GetDC(window);
         (window, hdc)
     }
 }
 
 pub fn process_events(drawer: &mut Drawer, ctx: &mut NkContext) {
-    unsafe {
-        if EVENTS.is_none() {
-            EVENTS = Some(Vec::new());
-        }
+    let wnd = match drawer.window() {
+        Some(wnd) => wnd,
+        None => return,
+    };
+
+    // the queue is not borrowed while an event is handled, handling may send new messages to the window
+    while let Some((msg, wparam, lparam)) = unsafe { window_data(wnd) }.and_then(|data| data.events.borrow_mut().pop_front()) {
+        drawer.handle_event(ctx, wnd, msg, wparam, lparam);
+    }
+}
 
-        for &mut (wnd, msg, wparam, lparam) in EVENTS.as_mut().unwrap() {
-            drawer.handle_event(ctx, wnd, msg, wparam, lparam);
+pub fn take_close_request(wnd: winapi::HWND) -> bool {
+    match unsafe { window_data(wnd) } {
+        Some(data) => {
+            let requested = data.close_requested.get();
+            data.close_requested.set(false);
+            requested
         }
+        None => false,
+    }
+}
+
+pub fn set_inline_ime(wnd: winapi::HWND, inline: bool) {
+    if let Some(data) = unsafe { window_data(wnd) } {
+        data.inline_ime.set(inline);
+    }
+}
+
+pub fn set_mouse_emulation(wnd: winapi::HWND, enabled: bool) {
+    if let Some(data) = unsafe { window_data(wnd) } {
+        data.mouse_emulation.set(enabled);
+    }
+}
 
-        EVENTS.as_mut().unwrap().clear();
+pub fn set_message_handler(wnd: winapi::HWND, handler: Option<MessageHandler>) {
+    if let Some(data) = unsafe { window_data(wnd) } {
+        *data.handler.borrow_mut() = handler;
     }
 }
 
@@ -47,7 +100,8 @@ fn register_window_class() -> Vec<u16> {
             cbWndExtra: 0,
             hInstance: kernel32::GetModuleHandleW(ptr::null()),
             hIcon: user32::LoadIconW(ptr::null_mut(), winapi::IDI_APPLICATION),
-            hCursor: user32::LoadCursorW(ptr::null_mut(), winapi::IDC_ARROW),
+            // the drawer sets the cursor on WM_SETCURSOR, a class cursor would make it flicker
+            hCursor: ptr::null_mut(),
             hbrBackground: ptr::null_mut(),
             lpszMenuName: ptr::null(),
             lpszClassName: class_name.as_ptr(),
@@ -59,7 +113,7 @@ fn register_window_class() -> Vec<u16> {
     }
 }
 
-unsafe fn create_window(window_name: &str, width: i32, height: i32) -> winapi::HWND {
+unsafe fn create_window(window_name: &str, width: i32, height: i32, managed: bool) -> winapi::HWND {
     let class_name = register_window_class();
 
     let mut rect = winapi::RECT {
@@ -72,6 +126,15 @@ unsafe fn create_window(window_name: &str, width: i32, height: i32) -> winapi::H
     let exstyle = winapi::WS_EX_APPWINDOW;
 
     user32::AdjustWindowRectEx(&mut rect, style, winapi::FALSE, exstyle);
+    // WM_NCCREATE takes the data out, if the window is never created it is dropped along with the slot
+    let data = Cell::new(Some(Box::new(WindowData {
+                                           events: RefCell::new(VecDeque::new()),
+                                           handler: RefCell::new(None),
+                                           managed: managed,
+                                           close_requested: Cell::new(false),
+                                           inline_ime: Cell::new(false),
+                                           mouse_emulation: Cell::new(true),
+                                       })));
     let window_name = OsStr::new(window_name)
         .encode_wide()
         .chain(Some(0).into_iter())
@@ -88,27 +151,102 @@ unsafe fn create_window(window_name: &str, width: i32, height: i32) -> winapi::H
                             ptr::null_mut(),
                             ptr::null_mut(),
                             kernel32::GetModuleHandleW(ptr::null()),
-                            ptr::null_mut())
+                            &data as *const Cell<Option<Box<WindowData>>> as winapi::LPVOID)
 }
 
 unsafe extern "system" fn callback(wnd: winapi::HWND, msg: winapi::UINT, wparam: winapi::WPARAM, lparam: winapi::LPARAM) -> winapi::LRESULT {
     match msg {
-        winapi::WM_DESTROY => {
-            user32::PostQuitMessage(0);
+        winapi::WM_NCCREATE => {
+            let create = &*(lparam as *const winapi::CREATESTRUCTW);
+            let slot = &*(create.lpCreateParams as *const Cell<Option<Box<WindowData>>>);
+            if let Some(data) = slot.take() {
+                user32::SetWindowLongPtrW(wnd, winapi::GWLP_USERDATA, Box::into_raw(data) as winapi::LONG_PTR);
+            }
+        }
+        winapi::WM_NCDESTROY => {
+            let data = user32::SetWindowLongPtrW(wnd, winapi::GWLP_USERDATA, 0) as *mut WindowData;
+            if !data.is_null() {
+                drop(Box::from_raw(data));
+            }
+            return user32::DefWindowProcW(wnd, msg, wparam, lparam);
+        }
+        _ => {}
+    }
+
+    // the first few messages of CreateWindowExW arrive before WM_NCCREATE
+    let data = match window_data(wnd) {
+        Some(data) => data,
+        None => return user32::DefWindowProcW(wnd, msg, wparam, lparam),
+    };
+
+    let managed = data.managed;
+    let inline_ime = data.inline_ime.get();
+    let mouse_emulation = data.mouse_emulation.get();
+
+    // taken out for the call, so messages the handler itself triggers skip it instead of aliasing it
+    let handler = data.handler.borrow_mut().take();
+    if let Some(mut handler) = handler {
+        let result = handler(wnd, msg, wparam, lparam);
+        // the handler may have destroyed the window and with it the data
+        if let Some(data) = window_data(wnd) {
+            let mut slot = data.handler.borrow_mut();
+            if slot.is_none() {
+                *slot = Some(handler);
+            }
+        }
+        if let Some(result) = result {
+            return result;
+        }
+    }
+
+    match msg {
+        winapi::WM_CLOSE if managed => {
+            if let Some(data) = window_data(wnd) {
+                data.close_requested.set(true);
+            }
             return 0;
         }
-        _ => {
-            if EVENTS.is_none() {
-                EVENTS = Some(Vec::new());
+        winapi::WM_IME_SETCONTEXT if inline_ime => {
+            push_event(wnd, msg, wparam, lparam);
+            return user32::DefWindowProcW(wnd, msg, wparam, lparam & !ime::ISC_SHOWUICOMPOSITIONWINDOW);
+        }
+        winapi::WM_IME_STARTCOMPOSITION |
+        winapi::WM_IME_COMPOSITION |
+        winapi::WM_IME_ENDCOMPOSITION if inline_ime => {
+            push_event(wnd, msg, wparam, lparam);
+            return 0;
+        }
+        winapi::WM_POINTERDOWN |
+        winapi::WM_POINTERUPDATE |
+        winapi::WM_POINTERUP |
+        winapi::WM_POINTERLEAVE |
+        winapi::WM_POINTERCAPTURECHANGED if !mouse_emulation => {
+            push_event(wnd, msg, wparam, lparam);
+            return 0;
+        }
+        winapi::WM_DESTROY => {
+            if !managed {
+                user32::PostQuitMessage(0);
             }
-            EVENTS
-                .as_mut()
-                .unwrap()
-                .push((wnd, msg, wparam, lparam));
+            return 0;
         }
+        winapi::WM_UNICHAR => {
+            push_event(wnd, msg, wparam, lparam);
+            // answering TRUE to the probe tells the sender that UTF-32 input is understood here
+            return (wparam == winapi::UNICODE_NOCHAR) as winapi::LRESULT;
+        }
+        _ => push_event(wnd, msg, wparam, lparam),
     }
 
     user32::DefWindowProcW(wnd, msg, wparam, lparam)
 }
 
-static mut EVENTS: Option<Vec<(winapi::HWND, winapi::UINT, winapi::WPARAM, winapi::LPARAM)>> = None;
+unsafe fn window_data<'a>(wnd: winapi::HWND) -> Option<&'a WindowData> {
+    (user32::GetWindowLongPtrW(wnd, winapi::GWLP_USERDATA) as *const WindowData).as_ref()
+}
+
+unsafe fn push_event(wnd: winapi::HWND, msg: winapi::UINT, wparam: winapi::WPARAM, lparam: winapi::LPARAM) {
+    if let Some(data) = window_data(wnd) {
+        data.events.borrow_mut().push_back((msg, wparam, lparam));
+    }
+}
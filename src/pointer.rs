@@ -0,0 +1,368 @@
+use super::nuklear_rust::NkButton;
+
+use super::winapi;
+use super::user32;
+
+use super::input::{InputEvent, MOUSE_OUTSIDE};
+
+use std::mem;
+
+const POINTER_MESSAGE_FLAG_INCONTACT: u32 = 0x0004;
+const POINTER_MESSAGE_FLAG_PRIMARY: u32 = 0x2000;
+
+// Mouse messages synthesized from touch and pen input carry this signature in their extra info.
+const MI_WP_SIGNATURE: usize = 0xFF515700;
+const SIGNATURE_MASK: usize = 0xFFFFFF00;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct POINTER_INFO {
+    pointerType: winapi::POINTER_INPUT_TYPE,
+    pointerId: u32,
+    frameId: u32,
+    pointerFlags: u32,
+    sourceDevice: winapi::HANDLE,
+    hwndTarget: winapi::HWND,
+    ptPixelLocation: winapi::POINT,
+    ptHimetricLocation: winapi::POINT,
+    ptPixelLocationRaw: winapi::POINT,
+    ptHimetricLocationRaw: winapi::POINT,
+    dwTime: winapi::DWORD,
+    historyCount: u32,
+    InputData: i32,
+    dwKeyStates: winapi::DWORD,
+    PerformanceCount: u64,
+    ButtonChangeType: i32,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct POINTER_PEN_INFO {
+    pointerInfo: POINTER_INFO,
+    penFlags: u32,
+    penMask: u32,
+    pressure: u32,
+    rotation: u32,
+    tiltX: i32,
+    tiltY: i32,
+}
+
+// user32 is already linked through user32-sys, which lacks these.
+extern "system" {
+    fn GetPointerPenInfo(pointer: u32, info: *mut POINTER_PEN_INFO) -> winapi::BOOL;
+    fn GetMessageExtraInfo() -> winapi::LPARAM;
+}
+
+pub fn is_pointer_message(msg: winapi::UINT) -> bool {
+    match msg {
+        winapi::WM_POINTERDOWN |
+        winapi::WM_POINTERUPDATE |
+        winapi::WM_POINTERUP |
+        winapi::WM_POINTERLEAVE |
+        winapi::WM_POINTERCAPTURECHANGED => true,
+        _ => false,
+    }
+}
+
+// Only valid while the message is the one last retrieved from the queue, which is why the event loop hands every message's
+// events over right after dispatching it instead of draining the window's queue once per frame.
+pub unsafe fn is_emulated_mouse_message(msg: winapi::UINT) -> bool {
+    msg >= winapi::WM_MOUSEFIRST && msg <= winapi::WM_MOUSELAST && (GetMessageExtraInfo() as usize & SIGNATURE_MASK) == MI_WP_SIGNATURE
+}
+
+// Pressure normalized to 0..1, None for anything but a pen.
+pub unsafe fn pen_pressure(pointer: u32) -> Option<f32> {
+    let mut kind = winapi::PT_POINTER;
+    if user32::GetPointerType(pointer, &mut kind) == 0 || kind != winapi::PT_PEN {
+        return None;
+    }
+    let mut info: POINTER_PEN_INFO = mem::zeroed();
+    if GetPointerPenInfo(pointer, &mut info) == 0 {
+        return None;
+    }
+    Some(info.pressure as f32 / 1024.0)
+}
+
+// Contacts come in through pointer ids; the primary one stands in for the left button unless Windows already emulates
+// the mouse, and two or more contacts pan, which scrolls by their centroid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerTracker {
+    drives_mouse: bool,
+    pixels_per_line: f32,
+    contacts: Vec<(u32, i32, i32)>,
+    pressing: Option<u32>,
+    pan: Option<(i32, i32)>,
+    pan_x: f32,
+    pan_y: f32,
+}
+
+impl PointerTracker {
+    pub fn new() -> PointerTracker {
+        PointerTracker {
+            drives_mouse: false,
+            pixels_per_line: 20.0,
+            contacts: Vec::new(),
+            pressing: None,
+            pan: None,
+            pan_x: 0.0,
+            pan_y: 0.0,
+        }
+    }
+
+    pub fn drives_mouse(&self) -> bool {
+        self.drives_mouse
+    }
+
+    pub fn set_drives_mouse(&mut self, drives_mouse: bool) {
+        self.drives_mouse = drives_mouse;
+    }
+
+    pub fn pixels_per_line(&self) -> f32 {
+        self.pixels_per_line
+    }
+
+    // Zero, negative or NaN distances would make pans scroll endlessly or backwards and are ignored.
+    pub fn set_pixels_per_line(&mut self, pixels: f32) {
+        if pixels > 0.0 {
+            self.pixels_per_line = pixels;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.contacts.clear();
+        self.pressing = None;
+        self.end_pan();
+    }
+
+    pub fn decode(&mut self, msg: winapi::UINT, wparam: winapi::WPARAM, x: i32, y: i32, events: &mut Vec<InputEvent>) {
+        let id = wparam as u16 as u32;
+        let flags = (wparam >> 16) as u16 as u32;
+        let primary = (flags & POINTER_MESSAGE_FLAG_PRIMARY) != 0;
+        match msg {
+            winapi::WM_POINTERDOWN => self.down(id, primary, x, y, events),
+            winapi::WM_POINTERUPDATE => {
+                if (flags & POINTER_MESSAGE_FLAG_INCONTACT) != 0 {
+                    self.moved(id, x, y, events);
+                } else if primary && self.drives_mouse && self.contacts.is_empty() {
+                    // a pen hovering over the surface
+                    events.push(InputEvent::Motion { x: x, y: y });
+                }
+            }
+            winapi::WM_POINTERUP => self.up(id, x, y, false, events),
+            winapi::WM_POINTERCAPTURECHANGED => self.up(id, x, y, true, events),
+            winapi::WM_POINTERLEAVE => {
+                if primary && self.drives_mouse && self.contacts.is_empty() {
+                    events.push(InputEvent::Motion {
+                                    x: MOUSE_OUTSIDE,
+                                    y: MOUSE_OUTSIDE,
+                                });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn down(&mut self, id: u32, primary: bool, x: i32, y: i32, events: &mut Vec<InputEvent>) {
+        self.contacts.retain(|&(contact, _, _)| contact != id);
+        self.contacts.push((id, x, y));
+
+        if self.contacts.len() == 1 {
+            if primary && self.drives_mouse {
+                self.pressing = Some(id);
+                events.push(InputEvent::Motion { x: x, y: y });
+                events.push(left(x, y, true));
+            }
+        } else {
+            // the first finger turned out to start a pan, it must not click what it landed on
+            if let Some(pressing) = self.pressing.take() {
+                let (x, y) = self.position(pressing).unwrap_or((x, y));
+                cancel_press(x, y, events);
+            }
+            // the centroid jumps with every added finger, the pan continues from where it is now
+            self.pan = Some(self.centroid());
+        }
+    }
+
+    fn moved(&mut self, id: u32, x: i32, y: i32, events: &mut Vec<InputEvent>) {
+        for contact in self.contacts.iter_mut() {
+            if contact.0 == id {
+                contact.1 = x;
+                contact.2 = y;
+            }
+        }
+
+        if let Some((last_x, last_y)) = self.pan {
+            let (center_x, center_y) = self.centroid();
+            self.pan = Some((center_x, center_y));
+            // content follows the fingers: dragging down reveals what is above, like turning the wheel forward
+            self.pan_x += (center_x - last_x) as f32 / self.pixels_per_line;
+            self.pan_y += (center_y - last_y) as f32 / self.pixels_per_line;
+            let (lines_x, lines_y) = (self.pan_x.trunc(), self.pan_y.trunc());
+            if lines_x != 0.0 || lines_y != 0.0 {
+                self.pan_x -= lines_x;
+                self.pan_y -= lines_y;
+                events.push(InputEvent::Scroll {
+                                x: lines_x,
+                                y: lines_y,
+                            });
+            }
+        } else if self.pressing == Some(id) {
+            events.push(InputEvent::Motion { x: x, y: y });
+        }
+    }
+
+    fn up(&mut self, id: u32, x: i32, y: i32, canceled: bool, events: &mut Vec<InputEvent>) {
+        self.contacts.retain(|&(contact, _, _)| contact != id);
+        if self.pressing == Some(id) {
+            self.pressing = None;
+            if canceled {
+                cancel_press(x, y, events);
+            } else {
+                events.push(InputEvent::Motion { x: x, y: y });
+                events.push(left(x, y, false));
+            }
+        }
+        if self.contacts.len() < 2 {
+            self.end_pan();
+        } else if self.pan.is_some() {
+            self.pan = Some(self.centroid());
+        }
+    }
+
+    fn end_pan(&mut self) {
+        self.pan = None;
+        self.pan_x = 0.0;
+        self.pan_y = 0.0;
+    }
+
+    fn position(&self, id: u32) -> Option<(i32, i32)> {
+        self.contacts.iter().find(|contact| contact.0 == id).map(|&(_, x, y)| (x, y))
+    }
+
+    fn centroid(&self) -> (i32, i32) {
+        let count = self.contacts.len().max(1) as i32;
+        let (x, y) = self.contacts.iter().fold((0, 0), |(x, y), &(_, cx, cy)| (x + cx, y + cy));
+        (x / count, y / count)
+    }
+}
+
+fn left(x: i32, y: i32, down: bool) -> InputEvent {
+    InputEvent::Button {
+        button: NkButton::NK_BUTTON_LEFT,
+        x: x,
+        y: y,
+        down: down,
+    }
+}
+
+// Releasing away from the widget keeps nuklear from treating the release as a click.
+fn cancel_press(x: i32, y: i32, events: &mut Vec<InputEvent>) {
+    events.push(InputEvent::Motion {
+                    x: MOUSE_OUTSIDE,
+                    y: MOUSE_OUTSIDE,
+                });
+    events.push(left(MOUSE_OUTSIDE, MOUSE_OUTSIDE, false));
+    events.push(InputEvent::Motion { x: x, y: y });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_per_line_must_be_positive() {
+        let mut tracker = PointerTracker::new();
+        tracker.set_pixels_per_line(0.0);
+        tracker.set_pixels_per_line(-10.0);
+        tracker.set_pixels_per_line(::std::f32::NAN);
+        assert_eq!(tracker.pixels_per_line(), 20.0);
+        tracker.set_pixels_per_line(10.0);
+        assert_eq!(tracker.pixels_per_line(), 10.0);
+    }
+
+    const PRIMARY: u32 = POINTER_MESSAGE_FLAG_PRIMARY | POINTER_MESSAGE_FLAG_INCONTACT;
+    const SECONDARY: u32 = POINTER_MESSAGE_FLAG_INCONTACT;
+
+    fn decode(tracker: &mut PointerTracker, msg: winapi::UINT, id: u32, flags: u32, x: i32, y: i32) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        tracker.decode(msg, (id | (flags << 16)) as winapi::WPARAM, x, y, &mut events);
+        events
+    }
+
+    fn touch_tracker() -> PointerTracker {
+        let mut tracker = PointerTracker::new();
+        tracker.set_drives_mouse(true);
+        tracker.set_pixels_per_line(10.0);
+        tracker
+    }
+
+    fn motion(x: i32, y: i32) -> InputEvent {
+        InputEvent::Motion { x: x, y: y }
+    }
+
+    fn scroll(x: f32, y: f32) -> InputEvent {
+        InputEvent::Scroll { x: x, y: y }
+    }
+
+    #[test]
+    fn primary_contact_drives_the_left_button() {
+        let mut tracker = touch_tracker();
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERDOWN, 1, PRIMARY, 10, 20), vec![motion(10, 20), left(10, 20, true)]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, PRIMARY, 15, 25), vec![motion(15, 25)]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 1, PRIMARY, 15, 25), vec![motion(15, 25), left(15, 25, false)]);
+
+        // a hovering pen moves the pointer without pressing
+        let hover = POINTER_MESSAGE_FLAG_PRIMARY;
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, hover, 30, 40), vec![motion(30, 40)]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERLEAVE, 1, hover, 30, 40), vec![motion(MOUSE_OUTSIDE, MOUSE_OUTSIDE)]);
+
+        // with mouse emulation on, Windows synthesizes the mouse messages itself
+        let mut tracker = PointerTracker::new();
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERDOWN, 1, PRIMARY, 10, 20), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 1, PRIMARY, 10, 20), vec![]);
+    }
+
+    #[test]
+    fn second_contact_cancels_the_press_and_pans() {
+        let mut tracker = touch_tracker();
+        decode(&mut tracker, winapi::WM_POINTERDOWN, 1, PRIMARY, 10, 20);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERDOWN, 2, SECONDARY, 30, 20),
+                   vec![motion(MOUSE_OUTSIDE, MOUSE_OUTSIDE), left(MOUSE_OUTSIDE, MOUSE_OUTSIDE, false), motion(10, 20)]);
+
+        // the centroid moves by half of what one finger moves, content follows it
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 2, SECONDARY, 50, 20), vec![scroll(1.0, 0.0)]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, PRIMARY, 10, 0), vec![scroll(0.0, -1.0)]);
+        // fractions of a line accumulate
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, PRIMARY, 10, 10), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, PRIMARY, 10, 20), vec![scroll(0.0, 1.0)]);
+
+        // lifting the fingers neither clicks nor scrolls
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 2, SECONDARY, 50, 20), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, PRIMARY, 10, 90), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 1, PRIMARY, 10, 90), vec![]);
+    }
+
+    #[test]
+    fn contact_changes_rebase_the_pan() {
+        let mut tracker = touch_tracker();
+        decode(&mut tracker, winapi::WM_POINTERDOWN, 1, SECONDARY, 0, 0);
+        decode(&mut tracker, winapi::WM_POINTERDOWN, 2, SECONDARY, 20, 0);
+
+        // a third finger moves the centroid from 10 to 40 without scrolling
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERDOWN, 3, SECONDARY, 100, 0), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 3, SECONDARY, 130, 0), vec![scroll(1.0, 0.0)]);
+
+        // and lifting it moves the centroid back to 10
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 3, SECONDARY, 130, 0), vec![]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUPDATE, 1, SECONDARY, 20, 0), vec![scroll(1.0, 0.0)]);
+    }
+
+    #[test]
+    fn capture_loss_cancels_the_press() {
+        let mut tracker = touch_tracker();
+        decode(&mut tracker, winapi::WM_POINTERDOWN, 1, PRIMARY, 10, 20);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERCAPTURECHANGED, 1, PRIMARY, 12, 22),
+                   vec![motion(MOUSE_OUTSIDE, MOUSE_OUTSIDE), left(MOUSE_OUTSIDE, MOUSE_OUTSIDE, false), motion(12, 22)]);
+        assert_eq!(decode(&mut tracker, winapi::WM_POINTERUP, 1, PRIMARY, 12, 22), vec![]);
+    }
+}
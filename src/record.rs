@@ -0,0 +1,356 @@
+use super::nuklear_rust::{NkContext, NkKey, NkButton};
+
+use super::input::{InputEvent, XButton, apply_event};
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+const KEYS: &'static [NkKey] = &[NkKey::NK_KEY_SHIFT,
+                                 NkKey::NK_KEY_CTRL,
+                                 NkKey::NK_KEY_DEL,
+                                 NkKey::NK_KEY_ENTER,
+                                 NkKey::NK_KEY_TAB,
+                                 NkKey::NK_KEY_BACKSPACE,
+                                 NkKey::NK_KEY_COPY,
+                                 NkKey::NK_KEY_CUT,
+                                 NkKey::NK_KEY_PASTE,
+                                 NkKey::NK_KEY_UP,
+                                 NkKey::NK_KEY_DOWN,
+                                 NkKey::NK_KEY_LEFT,
+                                 NkKey::NK_KEY_RIGHT,
+                                 NkKey::NK_KEY_TEXT_INSERT_MODE,
+                                 NkKey::NK_KEY_TEXT_REPLACE_MODE,
+                                 NkKey::NK_KEY_TEXT_RESET_MODE,
+                                 NkKey::NK_KEY_TEXT_LINE_START,
+                                 NkKey::NK_KEY_TEXT_LINE_END,
+                                 NkKey::NK_KEY_TEXT_START,
+                                 NkKey::NK_KEY_TEXT_END,
+                                 NkKey::NK_KEY_TEXT_UNDO,
+                                 NkKey::NK_KEY_TEXT_REDO,
+                                 NkKey::NK_KEY_TEXT_SELECT_ALL,
+                                 NkKey::NK_KEY_TEXT_WORD_LEFT,
+                                 NkKey::NK_KEY_TEXT_WORD_RIGHT,
+                                 NkKey::NK_KEY_SCROLL_START,
+                                 NkKey::NK_KEY_SCROLL_END,
+                                 NkKey::NK_KEY_SCROLL_DOWN,
+                                 NkKey::NK_KEY_SCROLL_UP];
+
+const BUTTONS: &'static [NkButton] = &[NkButton::NK_BUTTON_LEFT, NkButton::NK_BUTTON_MIDDLE, NkButton::NK_BUTTON_RIGHT, NkButton::NK_BUTTON_DOUBLE];
+
+// One line per frame start and per event: "<frame> <millis> frame" or "<frame> <millis> <event> <args...>".
+pub struct InputRecorder {
+    out: Box<Write>,
+    start: Instant,
+    frames: u64,
+    error: Option<io::Error>,
+}
+
+impl InputRecorder {
+    pub fn new(out: Box<Write>) -> InputRecorder {
+        InputRecorder {
+            out: out,
+            start: Instant::now(),
+            frames: 0,
+            error: None,
+        }
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<InputRecorder> {
+        let file = File::create(path)?;
+        Ok(InputRecorder::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn begin_frame(&mut self) {
+        let frame = self.frames;
+        self.frames += 1;
+        self.write_line(frame, "frame".to_owned());
+    }
+
+    pub fn record(&mut self, event: &InputEvent) {
+        if self.frames == 0 {
+            self.begin_frame();
+        }
+        let frame = self.frames - 1;
+        self.write_line(frame, format_event(event));
+    }
+
+    // Writing stops at the first failure, which is reported here.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.out.flush(),
+        }
+    }
+
+    fn write_line(&mut self, frame: u64, line: String) {
+        if self.error.is_some() {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        let millis = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1000000) as u64;
+        if let Err(error) = writeln!(self.out, "{} {} {}", frame, millis, line) {
+            self.error = Some(error);
+        }
+    }
+}
+
+pub struct RecordedFrame {
+    pub index: u64,
+    pub millis: u64,
+    pub events: Vec<InputEvent>,
+}
+
+pub struct InputReplayer {
+    frames: VecDeque<RecordedFrame>,
+}
+
+impl InputReplayer {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<InputReplayer> {
+        let file = File::open(path)?;
+        InputReplayer::read(BufReader::new(file))
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<InputReplayer> {
+        let mut frames: VecDeque<RecordedFrame> = VecDeque::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid input record at line {}: {}", number + 1, line));
+
+            let mut fields = line.split_whitespace();
+            let index = match fields.next() {
+                Some(index) => index.parse::<u64>().map_err(|_| invalid())?,
+                None => continue,
+            };
+            let millis = fields.next().and_then(|m| m.parse::<u64>().ok()).ok_or_else(&invalid)?;
+            let kind = fields.next().ok_or_else(&invalid)?;
+            let args = fields.collect::<Vec<_>>();
+
+            if kind == "frame" {
+                frames.push_back(RecordedFrame {
+                                     index: index,
+                                     millis: millis,
+                                     events: Vec::new(),
+                                 });
+                continue;
+            }
+
+            let event = parse_event(kind, &args).ok_or_else(&invalid)?;
+            match frames.back_mut() {
+                Some(ref mut frame) if frame.index == index => frame.events.push(event),
+                _ => {
+                    frames.push_back(RecordedFrame {
+                                         index: index,
+                                         millis: millis,
+                                         events: vec![event],
+                                     })
+                }
+            }
+        }
+
+        Ok(InputReplayer { frames: frames })
+    }
+
+    pub fn remaining_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn next_frame(&self) -> Option<&RecordedFrame> {
+        self.frames.front()
+    }
+
+    // Feeds the next recorded frame into the context the way Drawer::process_events does; false once the record is exhausted.
+    pub fn play_frame(&mut self, ctx: &mut NkContext) -> bool {
+        match self.frames.pop_front() {
+            Some(frame) => {
+                ctx.input_begin();
+                for event in frame.events.iter() {
+                    apply_event(ctx, event);
+                }
+                ctx.input_end();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn format_event(event: &InputEvent) -> String {
+    match *event {
+        InputEvent::Key { key, down } => format!("key {:?} {}", key, down as u8),
+        InputEvent::Char(c) => format!("char {}", c as u32),
+        InputEvent::Button { button, x, y, down } => format!("button {:?} {} {} {}", button, x, y, down as u8),
+        InputEvent::Motion { x, y } => format!("motion {} {}", x, y),
+        InputEvent::Scroll { x, y } => format!("scroll {} {}", x, y),
+        InputEvent::XButton { button, down } => format!("xbutton {:?} {}", button, down as u8),
+        InputEvent::Resize { width, height } => format!("resize {} {}", width, height),
+        InputEvent::Focus(focused) => format!("focus {}", focused as u8),
+    }
+}
+
+fn parse_event(kind: &str, args: &[&str]) -> Option<InputEvent> {
+    let int = |i: usize| args.get(i).and_then(|a| a.parse::<i32>().ok());
+    let float = |i: usize| args.get(i).and_then(|a| a.parse::<f32>().ok());
+    let flag = |i: usize| match args.get(i) {
+        Some(&"1") => Some(true),
+        Some(&"0") => Some(false),
+        _ => None,
+    };
+    let name = |i: usize| args.get(i).map(|a| *a).unwrap_or("");
+
+    match kind {
+        "key" => {
+            let key = KEYS.iter().find(|k| format!("{:?}", k) == name(0));
+            match (key, flag(1)) {
+                (Some(&key), Some(down)) => Some(InputEvent::Key { key: key, down: down }),
+                _ => None,
+            }
+        }
+        "char" => {
+            args.get(0)
+                .and_then(|a| a.parse::<u32>().ok())
+                .and_then(::std::char::from_u32)
+                .map(InputEvent::Char)
+        }
+        "button" => {
+            let button = BUTTONS.iter().find(|b| format!("{:?}", b) == name(0));
+            match (button, int(1), int(2), flag(3)) {
+                (Some(&button), Some(x), Some(y), Some(down)) => {
+                    Some(InputEvent::Button {
+                             button: button,
+                             x: x,
+                             y: y,
+                             down: down,
+                         })
+                }
+                _ => None,
+            }
+        }
+        "motion" => {
+            match (int(0), int(1)) {
+                (Some(x), Some(y)) => Some(InputEvent::Motion { x: x, y: y }),
+                _ => None,
+            }
+        }
+        "scroll" => {
+            match (float(0), float(1)) {
+                (Some(x), Some(y)) => Some(InputEvent::Scroll { x: x, y: y }),
+                _ => None,
+            }
+        }
+        "xbutton" => {
+            let button = match name(0) {
+                "Back" => Some(XButton::Back),
+                "Forward" => Some(XButton::Forward),
+                _ => None,
+            };
+            match (button, flag(1)) {
+                (Some(button), Some(down)) => Some(InputEvent::XButton { button: button, down: down }),
+                _ => None,
+            }
+        }
+        "resize" => {
+            match (int(0), int(1)) {
+                (Some(width), Some(height)) => {
+                    Some(InputEvent::Resize {
+                             width: width as u16,
+                             height: height as u16,
+                         })
+                }
+                _ => None,
+            }
+        }
+        "focus" => flag(0).map(InputEvent::Focus),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_event_survives_a_round_trip() {
+        let frames = vec![vec![InputEvent::Key {
+                                   key: NkKey::NK_KEY_TEXT_WORD_LEFT,
+                                   down: true,
+                               },
+                               InputEvent::Char('\u{1F600}'),
+                               InputEvent::Button {
+                                   button: NkButton::NK_BUTTON_DOUBLE,
+                                   x: -5,
+                                   y: 7,
+                                   down: false,
+                               }],
+                          vec![],
+                          vec![InputEvent::Motion { x: -32768, y: 12 },
+                               InputEvent::Scroll { x: -0.5, y: 3.0 },
+                               InputEvent::XButton {
+                                   button: XButton::Forward,
+                                   down: true,
+                               },
+                               InputEvent::Resize {
+                                   width: 640,
+                                   height: 480,
+                               },
+                               InputEvent::Focus(false)]];
+
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut recorder = InputRecorder::new(Box::new(Shared(buffer.clone())));
+        for events in frames.iter() {
+            recorder.begin_frame();
+            for event in events.iter() {
+                recorder.record(event);
+            }
+        }
+        recorder.finish().unwrap();
+
+        let data = buffer.borrow().clone();
+        let mut replayer = InputReplayer::read(Cursor::new(data)).unwrap();
+        assert_eq!(replayer.remaining_frames(), frames.len());
+        for (index, events) in frames.iter().enumerate() {
+            let frame = replayer.frames.pop_front().unwrap();
+            assert_eq!(frame.index, index as u64);
+            assert_eq!(&frame.events, events);
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert!(InputReplayer::read(Cursor::new("0 0 frame\n0 0 key NK_KEY_NONE 1\n")).is_err());
+        assert!(InputReplayer::read(Cursor::new("0 0 button NK_BUTTON_LEFT 1 2\n")).is_err());
+        assert!(InputReplayer::read(Cursor::new("0 0 frame\n\n")).unwrap().remaining_frames() == 1);
+    }
+
+    // a key or button missing from the tables is still recorded, but then makes the whole record unreadable
+    #[test]
+    fn every_key_and_button_is_listed() {
+        let mut keys = KEYS.iter().map(|&key| key as i32).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys, (NkKey::NK_KEY_NONE as i32 + 1..NkKey::NK_KEY_MAX as i32).collect::<Vec<_>>());
+
+        let mut buttons = BUTTONS.iter().map(|&button| button as i32).collect::<Vec<_>>();
+        buttons.sort();
+        buttons.dedup();
+        assert_eq!(buttons, (0..NkButton::NK_BUTTON_MAX as i32).collect::<Vec<_>>());
+    }
+}
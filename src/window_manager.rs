@@ -0,0 +1,150 @@
+use super::nuklear_rust::{NkContext, NkAllocator};
+
+use super::user32;
+
+use std::mem;
+
+use super::{Drawer, EventMode, FontID, bundle_window, own_window, pump_messages};
+
+pub type WindowID = usize;
+
+// The context refers to the drawer's fonts, so it is declared first to be dropped first.
+struct ManagedWindow {
+    context: NkContext,
+    drawer: Drawer,
+}
+
+// Owns any number of top-level windows, each with its own drawer and context. Closing a window only destroys that window,
+// the message loop ends after the last one is gone or once a quit was requested.
+pub struct WindowManager {
+    windows: Vec<Option<ManagedWindow>>,
+    closed: Vec<WindowID>,
+    quit: bool,
+}
+
+impl WindowManager {
+    pub fn new() -> WindowManager {
+        WindowManager {
+            windows: Vec::new(),
+            closed: Vec::new(),
+            quit: false,
+        }
+    }
+
+    pub fn create_window(&mut self, window_name: &str, width: u16, height: u16, font_name: &str, font_size: u16, allocator: &mut NkAllocator) -> (WindowID, FontID) {
+        let (hwnd, hdc) = own_window::create_managed_env(window_name, width, height);
+
+        let (drawer, context, font_id) = bundle_window(hwnd, hdc, width, height, |drawer| drawer.new_font(font_name, font_size), allocator);
+        self.windows.push(Some(ManagedWindow {
+                                   context: context,
+                                   drawer: drawer,
+                               }));
+        (self.windows.len() - 1, font_id)
+    }
+
+    pub fn windows(&self) -> Vec<WindowID> {
+        self.windows
+            .iter()
+            .enumerate()
+            .filter(|&(_, window)| window.is_some())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn get_mut(&mut self, id: WindowID) -> Option<(&mut Drawer, &mut NkContext)> {
+        match self.windows.get_mut(id) {
+            Some(&mut Some(ref mut window)) => Some((&mut window.drawer, &mut window.context)),
+            _ => None,
+        }
+    }
+
+    pub fn close_window(&mut self, id: WindowID) {
+        let window = match self.windows.get_mut(id) {
+            Some(window) => window.take(),
+            None => None,
+        };
+        if let Some(window) = window {
+            let wnd = window.drawer.window();
+            let dc = window.drawer.window_dc;
+            drop(window);
+            if let Some(wnd) = wnd {
+                unsafe {
+                    user32::ReleaseDC(wnd, dc);
+                    if user32::IsWindow(wnd) != 0 {
+                        user32::DestroyWindow(wnd);
+                    }
+                }
+            }
+        }
+    }
+
+    // The windows process_events closed since the last call, so the application can drop what it kept for them.
+    pub fn take_closed_windows(&mut self) -> Vec<WindowID> {
+        mem::replace(&mut self.closed, Vec::new())
+    }
+
+    pub fn request_quit(&mut self) {
+        self.quit = true;
+    }
+
+    pub fn process_events(&mut self) -> bool {
+        self.process_events_with(EventMode::Block)
+    }
+
+    // One frame for every window: waits as the mode says, hands each window its own events and closes the windows whose close
+    // was requested. False once the application should end.
+    pub fn process_events_with(&mut self, mode: EventMode) -> bool {
+        // with no window left GetMessageW would wait for a message that never comes
+        if self.quit || self.windows.iter().all(|window| window.is_none()) {
+            return false;
+        }
+
+        for window in self.windows.iter_mut().filter_map(|window| window.as_mut()) {
+            window.drawer.begin_input_frame(&mut window.context);
+        }
+        let running = {
+            let windows = &mut self.windows;
+            unsafe {
+                pump_messages(mode,
+                              &mut || {
+                    for window in windows.iter_mut().filter_map(|window| window.as_mut()) {
+                        own_window::process_events(&mut window.drawer, &mut window.context);
+                    }
+                })
+            }
+        };
+        for window in self.windows.iter_mut().filter_map(|window| window.as_mut()) {
+            window.drawer.end_input_frame(&mut window.context);
+        }
+        if !running {
+            self.quit = true;
+            return false;
+        }
+
+        for id in 0..self.windows.len() {
+            let close = match self.windows[id] {
+                Some(ref window) => {
+                    match window.drawer.window() {
+                        Some(wnd) => own_window::take_close_request(wnd) || unsafe { user32::IsWindow(wnd) == 0 },
+                        None => true,
+                    }
+                }
+                None => false,
+            };
+            if close {
+                self.close_window(id);
+                self.closed.push(id);
+            }
+        }
+
+        !self.quit && self.windows.iter().any(|window| window.is_some())
+    }
+}
+
+impl Drop for WindowManager {
+    fn drop(&mut self) {
+        for id in 0..self.windows.len() {
+            self.close_window(id);
+        }
+    }
+}